@@ -0,0 +1,312 @@
+use serde::{Deserialize, Serialize};
+
+use crate::engine::Input;
+
+// how many keys or buttons one action can have on each device. binding
+// another pushes out the oldest.
+pub const MAX_BINDINGS: usize = 3;
+
+// always pauses on top of whatever pause is bound to, so there's no clearing
+// every way out of a game. it can't be bound to anything else.
+pub const PAUSE_KEY: &str = "Escape";
+
+// something the player can do in a game, whatever it's bound to
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    HardDrop,
+    RotateCW,
+    RotateCCW,
+    Rotate180,
+    Hold,
+    Undo,
+    Pause,
+}
+
+impl Action {
+    pub const ALL: [Action; 10] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::SoftDrop,
+        Action::HardDrop,
+        Action::RotateCW,
+        Action::RotateCCW,
+        Action::Rotate180,
+        Action::Hold,
+        Action::Undo,
+        Action::Pause,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move Left",
+            Action::MoveRight => "Move Right",
+            Action::SoftDrop => "Soft Drop",
+            Action::HardDrop => "Hard Drop",
+            Action::RotateCW => "Rotate CW",
+            Action::RotateCCW => "Rotate CCW",
+            Action::Rotate180 => "Rotate 180",
+            Action::Hold => "Hold",
+            Action::Undo => "Undo",
+            Action::Pause => "Pause",
+        }
+    }
+
+    // the engine input this drives, or None for the ones the scene handles
+    pub fn input(self) -> Option<Input> {
+        match self {
+            Action::MoveLeft => Some(Input::Left),
+            Action::MoveRight => Some(Input::Right),
+            Action::SoftDrop => Some(Input::Down),
+            Action::HardDrop => Some(Input::Up),
+            Action::RotateCW => Some(Input::RotateRight),
+            Action::RotateCCW => Some(Input::RotateLeft),
+            Action::Rotate180 => Some(Input::Rotate180),
+            Action::Hold => Some(Input::Hold),
+            Action::Undo => Some(Input::Undo),
+            Action::Pause => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Device {
+    Keyboard,
+    Gamepad,
+}
+
+impl Device {
+    pub fn name(self) -> &'static str {
+        match self {
+            Device::Keyboard => "Keyboard",
+            Device::Gamepad => "Gamepad",
+        }
+    }
+}
+
+// the keys and buttons for one action, by the names ggez gives them, like
+// "LShift" or "DPadLeft"
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Binding {
+    pub keyboard: Vec<String>,
+    pub gamepad: Vec<String>,
+}
+
+impl Binding {
+    fn new(keyboard: &[&str], gamepad: &[&str]) -> Self {
+        Binding {
+            keyboard: keyboard.iter().map(|k| k.to_string()).collect(),
+            gamepad: gamepad.iter().map(|b| b.to_string()).collect(),
+        }
+    }
+
+    pub fn names(&self, device: Device) -> &[String] {
+        match device {
+            Device::Keyboard => &self.keyboard,
+            Device::Gamepad => &self.gamepad,
+        }
+    }
+
+    fn names_mut(&mut self, device: Device) -> &mut Vec<String> {
+        match device {
+            Device::Keyboard => &mut self.keyboard,
+            Device::Gamepad => &mut self.gamepad,
+        }
+    }
+}
+
+// what every action is bound to. a field each, rather than a map keyed by
+// action, since toml only allows string keys.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bindings {
+    pub move_left: Binding,
+    pub move_right: Binding,
+    pub soft_drop: Binding,
+    pub hard_drop: Binding,
+    pub rotate_cw: Binding,
+    pub rotate_ccw: Binding,
+    pub rotate_180: Binding,
+    pub hold: Binding,
+    pub undo: Binding,
+    pub pause: Binding,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            move_left: Binding::new(&["Left"], &["DPadLeft"]),
+            move_right: Binding::new(&["Right"], &["DPadRight"]),
+            soft_drop: Binding::new(&["Down"], &["DPadDown"]),
+            hard_drop: Binding::new(&["Space", "Up"], &["DPadUp"]),
+            rotate_cw: Binding::new(&["X"], &["South"]),
+            rotate_ccw: Binding::new(&["Z", "LControl"], &["West"]),
+            rotate_180: Binding::new(&["A"], &["North"]),
+            hold: Binding::new(&["C", "LShift"], &["LeftTrigger", "RightTrigger"]),
+            undo: Binding::new(&["Back"], &["Select"]),
+            pause: Binding::new(&["P"], &["Start"]),
+        }
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> &Binding {
+        match action {
+            Action::MoveLeft => &self.move_left,
+            Action::MoveRight => &self.move_right,
+            Action::SoftDrop => &self.soft_drop,
+            Action::HardDrop => &self.hard_drop,
+            Action::RotateCW => &self.rotate_cw,
+            Action::RotateCCW => &self.rotate_ccw,
+            Action::Rotate180 => &self.rotate_180,
+            Action::Hold => &self.hold,
+            Action::Undo => &self.undo,
+            Action::Pause => &self.pause,
+        }
+    }
+
+    fn get_mut(&mut self, action: Action) -> &mut Binding {
+        match action {
+            Action::MoveLeft => &mut self.move_left,
+            Action::MoveRight => &mut self.move_right,
+            Action::SoftDrop => &mut self.soft_drop,
+            Action::HardDrop => &mut self.hard_drop,
+            Action::RotateCW => &mut self.rotate_cw,
+            Action::RotateCCW => &mut self.rotate_ccw,
+            Action::Rotate180 => &mut self.rotate_180,
+            Action::Hold => &mut self.hold,
+            Action::Undo => &mut self.undo,
+            Action::Pause => &mut self.pause,
+        }
+    }
+
+    // every action the key or button called `name` is bound to
+    pub fn actions(&self, device: Device, name: &str) -> Vec<Action> {
+        if device == Device::Keyboard && name == PAUSE_KEY {
+            return vec![Action::Pause];
+        }
+        Action::ALL
+            .iter()
+            .cloned()
+            .filter(|&a| self.get(a).names(device).iter().any(|n| n == name))
+            .collect()
+    }
+
+    // adds `name` to the action, taking it away from whatever had it before
+    pub fn bind(&mut self, device: Device, action: Action, name: &str) {
+        for &other in Action::ALL.iter() {
+            self.get_mut(other).names_mut(device).retain(|n| n != name);
+        }
+        let names = self.get_mut(action).names_mut(device);
+        names.push(name.to_string());
+        if names.len() > MAX_BINDINGS {
+            names.remove(0);
+        }
+    }
+
+    pub fn clear(&mut self, device: Device, action: Action) {
+        self.get_mut(action).names_mut(device).clear();
+    }
+
+    // what the action is bound to, with PAUSE_KEY first for pause
+    pub fn listed(&self, device: Device, action: Action) -> Vec<String> {
+        let fixed = if device == Device::Keyboard && action == Action::Pause {
+            Some(PAUSE_KEY.to_string())
+        } else {
+            None
+        };
+        let bound = self.get(action).names(device).iter();
+        fixed
+            .into_iter()
+            .chain(bound.filter(|&n| n != PAUSE_KEY).cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_bind_every_action_once() {
+        let bindings = Bindings::default();
+        for &device in [Device::Keyboard, Device::Gamepad].iter() {
+            for &action in Action::ALL.iter() {
+                let names = bindings.get(action).names(device);
+                assert!(!names.is_empty(), "{:?} {:?}", device, action);
+                for name in names {
+                    assert_eq!(bindings.actions(device, name), vec![action]);
+                }
+            }
+        }
+        assert_eq!(
+            bindings.actions(Device::Keyboard, "Space"),
+            vec![Action::HardDrop]
+        );
+        assert!(bindings.actions(Device::Keyboard, "F1").is_empty());
+    }
+
+    #[test]
+    fn binding_moves_a_key_between_actions() {
+        let mut bindings = Bindings::default();
+        bindings.bind(Device::Keyboard, Action::Hold, "Space");
+        assert_eq!(
+            bindings.actions(Device::Keyboard, "Space"),
+            vec![Action::Hold]
+        );
+        assert_eq!(bindings.hard_drop.keyboard, vec!["Up".to_string()]);
+        // the gamepad is left alone
+        assert_eq!(bindings.hold.gamepad.len(), 2);
+
+        bindings.bind(Device::Keyboard, Action::Hold, "H");
+        assert_eq!(bindings.hold.keyboard, vec!["LShift", "Space", "H"]);
+        bindings.clear(Device::Keyboard, Action::Hold);
+        assert!(bindings.actions(Device::Keyboard, "H").is_empty());
+    }
+
+    #[test]
+    fn the_pause_key_cant_be_cleared() {
+        let mut bindings = Bindings::default();
+        assert_eq!(
+            bindings.listed(Device::Keyboard, Action::Pause),
+            vec![PAUSE_KEY, "P"]
+        );
+        bindings.clear(Device::Keyboard, Action::Pause);
+        assert_eq!(
+            bindings.actions(Device::Keyboard, PAUSE_KEY),
+            vec![Action::Pause]
+        );
+        assert_eq!(
+            bindings.listed(Device::Keyboard, Action::Pause),
+            vec![PAUSE_KEY]
+        );
+        // or taken by another action in a hand-edited file
+        bindings.hold.keyboard.push(PAUSE_KEY.to_string());
+        assert_eq!(
+            bindings.actions(Device::Keyboard, PAUSE_KEY),
+            vec![Action::Pause]
+        );
+        assert_eq!(
+            bindings.listed(Device::Gamepad, Action::Pause),
+            vec!["Start"]
+        );
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut bindings = Bindings::default();
+        bindings.bind(Device::Gamepad, Action::Rotate180, "RightTrigger2");
+        let text = toml::to_string(&toml::Value::try_from(&bindings).unwrap()).unwrap();
+        assert_eq!(toml::from_str::<Bindings>(&text).unwrap(), bindings);
+        // actions left out of the file keep their defaults
+        let partial: Bindings = toml::from_str("[hold]\nkeyboard = [\"H\"]\n").unwrap();
+        assert_eq!(partial.hold.keyboard, vec!["H"]);
+        assert_eq!(partial.pause, Bindings::default().pause);
+        // typos are caught rather than leaving the action unbound
+        assert!(toml::from_str::<Bindings>("[hold]\nkeybord = [\"H\"]\n").is_err());
+        assert!(toml::from_str::<Bindings>("[hodl]\nkeyboard = [\"H\"]\n").is_err());
+    }
+}
@@ -1,7 +1,20 @@
 pub const ASPECT_RATIO: f32 = 4.0 / 3.0;
-pub const BASE_SPEED: f32 = 1.0;
-pub const SPEED_PER_LEVEL: f32 = 0.5;
 pub const ROWS: usize = 24;
+pub const VISIBLE_ROWS: usize = 20;
+pub const BUFFER_ROWS: usize = ROWS - VISIBLE_ROWS;
 pub const COLUMNS: usize = 10;
-pub const MOVEMENT_DELAY: u64 = 75;
-pub const INPUT_DELAY: u64 = 200;
+pub const FRAME_RATE: f32 = 60.0;
+pub const MAX_GRAVITY: f32 = 20.0;
+pub const DAS: f32 = 0.17;
+pub const ARR: f32 = 0.05;
+pub const SOFT_DROP_FACTOR: f32 = 20.0;
+pub const LOCK_DELAY: f32 = 0.5;
+pub const LOCK_RESET_LIMIT: u32 = 15;
+pub const PREVIEW_COUNT: usize = 3;
+pub const MAX_PREVIEW_COUNT: usize = 6;
+pub const ANNOUNCE_TIME: f32 = 1.5;
+pub const COUNTDOWN: f32 = 3.0;
+pub const LEADERBOARD_SIZE: usize = 10;
+pub const MAX_START_LEVEL: u32 = 20;
+pub const UNDO_LIMIT: usize = 100;
+pub const NAME_LENGTH: usize = 3;
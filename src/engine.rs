@@ -0,0 +1,1660 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{
+    BUFFER_ROWS, COLUMNS, FRAME_RATE, LOCK_DELAY, LOCK_RESET_LIMIT, MAX_GRAVITY, PREVIEW_COUNT,
+    ROWS, UNDO_LIMIT,
+};
+use crate::garbage::{self, GARBAGE_STREAM};
+use crate::gravity::GravityKind;
+use crate::handling::{AutoShift, Handling};
+use crate::lock::{LockDelay, LockReset};
+use crate::mode::GameMode;
+use crate::piece::{Block, Direction, Piece, PieceGenerator, PieceType};
+use crate::position::Position;
+use crate::randomizer::RandomizerKind;
+use crate::rotation::RotationKind;
+use crate::scoring::{self, Chain, Clear, Spin};
+use crate::types::GridPosition;
+
+#[derive(Clone)]
+pub struct GridState(pub [[Option<Block>; COLUMNS]; ROWS]);
+
+impl fmt::Display for GridState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.0.iter() {
+            for cell in row.iter() {
+                match cell {
+                    Some(_) => write!(f, "x")?,
+                    None => write!(f, "o")?,
+                };
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// one of the buttons the game reads, whatever it's bound to
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Input {
+    Left,
+    Right,
+    Down,
+    Up,
+    RotateRight,
+    RotateLeft,
+    Rotate180,
+    Hold,
+    Undo,
+}
+
+impl Input {
+    pub const ALL: [Input; 9] = [
+        Input::Left,
+        Input::Right,
+        Input::Down,
+        Input::Up,
+        Input::RotateRight,
+        Input::RotateLeft,
+        Input::Rotate180,
+        Input::Hold,
+        Input::Undo,
+    ];
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct InputState {
+    pub left: bool,
+    pub right: bool,
+    pub down: bool,
+    pub up: bool,
+    pub rotate_right: bool,
+    pub rotate_left: bool,
+    pub rotate_180: bool,
+    pub hold: bool,
+    pub undo: bool,
+}
+
+impl InputState {
+    pub fn is_down(&self, input: Input) -> bool {
+        match input {
+            Input::Left => self.left,
+            Input::Right => self.right,
+            Input::Down => self.down,
+            Input::Up => self.up,
+            Input::RotateRight => self.rotate_right,
+            Input::RotateLeft => self.rotate_left,
+            Input::Rotate180 => self.rotate_180,
+            Input::Hold => self.hold,
+            Input::Undo => self.undo,
+        }
+    }
+
+    pub fn set(&mut self, input: Input, down: bool) {
+        let button = match input {
+            Input::Left => &mut self.left,
+            Input::Right => &mut self.right,
+            Input::Down => &mut self.down,
+            Input::Up => &mut self.up,
+            Input::RotateRight => &mut self.rotate_right,
+            Input::RotateLeft => &mut self.rotate_left,
+            Input::Rotate180 => &mut self.rotate_180,
+            Input::Hold => &mut self.hold,
+            Input::Undo => &mut self.undo,
+        };
+        *button = down;
+    }
+
+    // the buttons that are down now but weren't in `previous`
+    fn pressed_since(&self, previous: &InputState) -> InputState {
+        InputState {
+            left: self.left && !previous.left,
+            right: self.right && !previous.right,
+            down: self.down && !previous.down,
+            up: self.up && !previous.up,
+            rotate_right: self.rotate_right && !previous.rotate_right,
+            rotate_left: self.rotate_left && !previous.rotate_left,
+            rotate_180: self.rotate_180 && !previous.rotate_180,
+            hold: self.hold && !previous.hold,
+            undo: self.undo && !previous.undo,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameOptions {
+    pub mode: GameMode,
+    pub start_level: u32,
+    // how often garbage holes move from row to row, from 0 to 1
+    pub messiness: f32,
+    pub randomizer: RandomizerKind,
+    pub rotation: RotationKind,
+    pub gravity: GravityKind,
+    pub lock_delay: f32,
+    pub lock_reset: LockReset,
+    // seconds full rows stay on the board before everything above falls into
+    // their place, with no piece in play until they do
+    pub line_clear_delay: f32,
+    pub handling: Handling,
+    pub preview_count: usize,
+    // whether locking a piece partly above the visible field ends the game
+    pub partial_lock_out: bool,
+}
+
+impl Default for GameOptions {
+    fn default() -> Self {
+        GameOptions {
+            mode: GameMode::default(),
+            start_level: 1,
+            messiness: 0.25,
+            randomizer: RandomizerKind::default(),
+            rotation: RotationKind::default(),
+            gravity: GravityKind::default(),
+            lock_delay: LOCK_DELAY,
+            lock_reset: LockReset::Move(LOCK_RESET_LIMIT),
+            line_clear_delay: 0.0,
+            handling: Handling::default(),
+            preview_count: PREVIEW_COUNT,
+            partial_lock_out: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameEvent {
+    // a piece locked and cleared lines, or spun in without clearing any
+    LineClear(Clear),
+}
+
+impl GameEvent {
+    // the announcement for the HUD, if this one deserves any
+    pub fn name(&self) -> Option<String> {
+        match *self {
+            GameEvent::LineClear(ref clear) => clear.name(),
+        }
+    }
+}
+
+// the ways a game can be lost
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TopOut {
+    // a new piece spawned overlapping the stack
+    Block,
+    // a piece locked entirely in the hidden rows above the visible field
+    Lock,
+    // a piece locked with any of its blocks above the visible field
+    PartialLock,
+}
+
+impl TopOut {
+    pub fn name(self) -> &'static str {
+        match self {
+            TopOut::Block => "Block out",
+            TopOut::Lock => "Lock out",
+            TopOut::PartialLock => "Partial lock out",
+        }
+    }
+}
+
+// everything needed to put the game back the way it was when a piece spawned
+#[derive(Clone)]
+pub struct Snapshot {
+    grid: GridState,
+    held: Option<Piece>,
+    next: VecDeque<Piece>,
+    score: u32,
+    level: u32,
+    lines_cleared: u32,
+    chain: Chain,
+    pieces: u32,
+    garbage_left: u32,
+    generator: PieceGenerator,
+}
+
+// All of the rules of the game, without any knowledge of how it gets drawn.
+pub struct Game {
+    pub grid: GridState,
+    pub falling: Option<Piece>,
+    pub projection: Option<Piece>,
+    pub held: Option<Piece>,
+    pub next: VecDeque<Piece>,
+    pub score: u32,
+    pub level: u32,
+    pub lines_cleared: u32,
+    pub chain: Chain,
+    // seconds of play since the countdown ended, and pieces locked so far
+    pub time: f32,
+    pub pieces: u32,
+    // garbage rows still at the bottom of the grid
+    pub garbage_left: u32,
+    // why the game ended, if it did
+    pub top_out: Option<TopOut>,
+    options: GameOptions,
+    generator: PieceGenerator,
+    lock: LockDelay,
+    auto_shift: AutoShift,
+    last_input: InputState,
+    hold_used: bool,
+    last_kick: Option<usize>,
+    events: Vec<GameEvent>,
+    countdown: f32,
+    // one snapshot per piece that spawned, newest last, for undo
+    history: Vec<Snapshot>,
+    // full rows waiting out the line clear delay, and how long is left of it
+    clearing: Vec<usize>,
+    clear_delay: f32,
+}
+
+impl Game {
+    pub fn new(seed: u64, options: &GameOptions) -> Self {
+        let mut generator = PieceGenerator::new(seed, options.randomizer);
+        let next = (0..options.preview_count.max(1))
+            .map(|_| Piece::new(generator.next_type(), options.rotation))
+            .collect();
+        let mut game = Self {
+            grid: GridState([[None; COLUMNS]; ROWS]),
+            falling: None,
+            projection: None,
+            held: None,
+            next,
+            score: 0,
+            level: options.start_level.max(1),
+            lines_cleared: 0,
+            chain: Chain::default(),
+            time: 0.0,
+            pieces: 0,
+            garbage_left: 0,
+            top_out: None,
+            options: options.clone(),
+            generator,
+            lock: LockDelay::default(),
+            auto_shift: AutoShift::default(),
+            last_input: InputState::default(),
+            hold_used: false,
+            last_kick: None,
+            events: Vec::new(),
+            countdown: options.mode.countdown(),
+            history: Vec::new(),
+            clearing: Vec::new(),
+            clear_delay: 0.0,
+        };
+        if let Some(rows) = options.mode.garbage_rows() {
+            game.add_garbage(seed, rows as usize);
+        }
+        game
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.generator.seed()
+    }
+
+    pub fn step(&mut self, input: &InputState, dt: f32) {
+        if self.countdown > 0.0 {
+            // anything held through the countdown has to be pressed again
+            self.countdown -= dt;
+            self.last_input = input.clone();
+            return;
+        }
+        if self.is_complete() || self.top_out.is_some() {
+            return;
+        }
+        self.time += dt;
+        if let Some(limit) = self.options.mode.time_limit() {
+            self.time = self.time.min(limit);
+        }
+        if input.undo && !self.last_input.undo && self.options.mode.is_practice() {
+            self.undo();
+        }
+        if !self.clearing.is_empty() {
+            // buttons pressed in the meantime count once the next piece is out
+            self.clear_delay -= dt;
+            if self.clear_delay > 0.0 {
+                return;
+            }
+            self.collapse_rows();
+        }
+        self.swap_hold(input);
+        self.create_new_piece();
+        if self.top_out.is_none() {
+            self.update_piece_position(input, dt);
+        }
+        if self.top_out.is_some() && self.options.mode.is_practice() {
+            // practice goes on with an empty board instead of ending
+            self.grid = GridState([[None; COLUMNS]; ROWS]);
+            self.garbage_left = 0;
+            self.top_out = None;
+        }
+        self.update_projection();
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            grid: self.grid.clone(),
+            held: self.held.clone(),
+            next: self.next.clone(),
+            score: self.score,
+            level: self.level,
+            lines_cleared: self.lines_cleared,
+            chain: self.chain.clone(),
+            pieces: self.pieces,
+            garbage_left: self.garbage_left,
+            generator: self.generator.clone(),
+        }
+    }
+
+    // goes back to the moment the snapshot was taken, before the piece that
+    // was about to spawn came out
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.grid = snapshot.grid;
+        self.held = snapshot.held;
+        self.next = snapshot.next;
+        self.score = snapshot.score;
+        self.level = snapshot.level;
+        self.lines_cleared = snapshot.lines_cleared;
+        self.chain = snapshot.chain;
+        self.pieces = snapshot.pieces;
+        self.garbage_left = snapshot.garbage_left;
+        self.generator = snapshot.generator;
+        self.falling = None;
+        self.projection = None;
+        self.clearing.clear();
+        self.clear_delay = 0.0;
+        self.lock = LockDelay::default();
+        self.hold_used = false;
+        self.last_kick = None;
+    }
+
+    // takes back the last piece that was placed, returning false if there's
+    // nothing to take back
+    pub fn undo(&mut self) -> bool {
+        if self.history.len() < 2 {
+            return false;
+        }
+        // the newest snapshot is from when the falling piece spawned, and the
+        // one before it is from when the placed piece did
+        self.history.pop();
+        let snapshot = self.history.pop().unwrap();
+        self.restore(snapshot);
+        true
+    }
+
+    // pieces that can still be taken back
+    pub fn undo_count(&self) -> usize {
+        self.history.len().saturating_sub(1)
+    }
+
+    // rows per second at the current level, looked up fresh every step so a
+    // level up speeds up the piece that's already falling
+    pub fn gravity(&self) -> f32 {
+        self.options.gravity.curve().rows_per_second(self.level)
+    }
+
+    // seconds left before the game starts
+    pub fn countdown(&self) -> f32 {
+        self.countdown.max(0.0)
+    }
+
+    // whether the mode's goal has been reached, which ends the game as a win
+    pub fn is_complete(&self) -> bool {
+        let mode = self.options.mode;
+        let lines_done = match mode.line_goal() {
+            Some(goal) => self.lines_cleared >= goal,
+            None => false,
+        };
+        let time_done = match mode.time_limit() {
+            Some(limit) => self.time >= limit,
+            None => false,
+        };
+        let garbage_done = match mode.garbage_rows() {
+            Some(_) => self.garbage_left == 0,
+            None => false,
+        };
+        lines_done || time_done || garbage_done
+    }
+
+    pub fn pieces_per_second(&self) -> f32 {
+        if self.time > 0.0 {
+            self.pieces as f32 / self.time
+        } else {
+            0.0
+        }
+    }
+
+    // how far along the falling piece is to locking, from 0 to 1
+    pub fn lock_progress(&self) -> f32 {
+        self.lock.progress(self.options.lock_delay)
+    }
+
+    // everything that happened since the last call, oldest first
+    pub fn take_events(&mut self) -> Vec<GameEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    // whether the falling piece can still be swapped, which is once per piece
+    pub fn can_hold(&self) -> bool {
+        !self.hold_used
+    }
+
+    fn swap_hold(&mut self, input: &InputState) {
+        if !input.hold || self.last_input.hold || self.hold_used {
+            return;
+        }
+        if let Some(falling) = self.falling.take() {
+            // both pieces start over from the top, the way they first spawned
+            if let Some(held) = self.held.take() {
+                let p = self.spawn(held.piece_type);
+                self.enter(p);
+            }
+            self.held = Some(self.spawn(falling.piece_type));
+            self.projection = None;
+            self.lock = LockDelay::default();
+            self.last_kick = None;
+            self.hold_used = true;
+        }
+    }
+
+    fn spawn(&self, piece_type: PieceType) -> Piece {
+        Piece::new(piece_type, self.options.rotation)
+    }
+
+    // pieces appear above the field and drop straight into its first row if
+    // there's room. if they overlap the stack that's a block out.
+    fn enter(&mut self, mut p: Piece) {
+        let below = p.pos.move_down();
+        if !Game::is_valid_position(&self.grid, &p, &p.pos) {
+            self.top_out = Some(TopOut::Block);
+        } else if Game::is_valid_position(&self.grid, &p, &below) {
+            p.pos = below;
+        }
+        self.falling = Some(p);
+    }
+
+    fn create_new_piece(&mut self) {
+        if self.falling.is_none() {
+            // a piece coming out of an empty hold is still the same turn
+            if self.options.mode.is_practice() && !self.hold_used {
+                if self.history.len() == UNDO_LIMIT + 1 {
+                    self.history.remove(0);
+                }
+                self.history.push(self.snapshot());
+            }
+            let next = self.next.pop_front().unwrap();
+            let piece_type = self.generator.next_type();
+            let piece = self.spawn(piece_type);
+            self.next.push_back(piece);
+            self.enter(next);
+            self.lock = LockDelay::default();
+            self.last_kick = None;
+        }
+    }
+
+    fn lock_piece(&mut self, p: &Piece) {
+        let grid = &self.grid;
+        let occupied = |cell| Game::is_occupied(grid, cell);
+        let spin = scoring::t_spin(p, self.last_kick, &occupied);
+        let blocks = p.get_blocks(&p.pos);
+        let hidden = blocks
+            .iter()
+            .filter(|b| b.pos.grid_position().y < BUFFER_ROWS as i32)
+            .count();
+        if hidden == blocks.len() {
+            self.top_out = Some(TopOut::Lock);
+        } else if hidden > 0 && self.options.partial_lock_out {
+            self.top_out = Some(TopOut::PartialLock);
+        }
+        for block in blocks {
+            if let Some((x, y)) = get_grid_idx(block.pos) {
+                self.grid.0[y][x] = Some(Block::from_piece(p, block.pos));
+            }
+        }
+        self.projection = None;
+        self.hold_used = false;
+        self.pieces += 1;
+        self.clear_full_rows(spin);
+    }
+
+    // fills the bottom of the grid with rows that each have a single hole
+    fn add_garbage(&mut self, seed: u64, rows: usize) {
+        let rows = rows.min(ROWS);
+        let mut rng = Pcg32::new(seed, GARBAGE_STREAM);
+        let holes = garbage::garbage_holes(&mut rng, rows, self.options.messiness);
+        for (i, &hole) in holes.iter().enumerate() {
+            let y = ROWS - 1 - i;
+            for x in (0..COLUMNS).filter(|&x| x != hole) {
+                self.grid.0[y][x] = Some(Block {
+                    piece_type: PieceType::OShape,
+                    pos: Position::new(x as f32, y as f32),
+                });
+            }
+        }
+        self.garbage_left = rows as u32;
+    }
+
+    // scores the rows the last lock filled right away, and removes them once
+    // the line clear delay is over
+    fn clear_full_rows(&mut self, spin: Spin) {
+        let full: Vec<usize> = (0..ROWS)
+            .filter(|&i| self.grid.0[i].iter().all(|b| b.is_some()))
+            .collect();
+        let lines_cleared = full.len() as u32;
+        // garbage always sits in one piece at the very bottom
+        let garbage_top = ROWS - self.garbage_left as usize;
+        self.garbage_left -= full.iter().filter(|&&i| i >= garbage_top).count() as u32;
+        self.lines_cleared += lines_cleared;
+        // the clear is worth what it would be at the level it was made on,
+        // even if it's the one that levels up
+        let level = self.level;
+        self.level = level_for_lines(self.options.start_level, self.lines_cleared);
+        let perfect = self
+            .grid
+            .0
+            .iter()
+            .enumerate()
+            .all(|(i, row)| full.contains(&i) || row.iter().all(|b| b.is_none()));
+        let clear = self.chain.score(lines_cleared, spin, perfect, level);
+        self.score += clear.points;
+        if lines_cleared > 0 || spin != Spin::None {
+            self.events.push(GameEvent::LineClear(clear));
+        }
+
+        self.clearing = full;
+        self.clear_delay = self.options.line_clear_delay;
+        if self.clear_delay <= 0.0 {
+            self.collapse_rows();
+        }
+    }
+
+    fn collapse_rows(&mut self) {
+        // top to bottom, so the rows still to go don't move
+        for i in std::mem::take(&mut self.clearing) {
+            for j in (0..(i + 1)).rev() {
+                for k in 0..self.grid.0[j].len() {
+                    if j > 0 {
+                        self.grid.0[j][k] = match self.grid.0[j - 1][k] {
+                            Some(mut b) => {
+                                b.pos = Position::new(k as f32, j as f32);
+                                Some(b)
+                            }
+                            None => None,
+                        }
+                    } else {
+                        self.grid.0[j][k] = None;
+                    }
+                }
+            }
+        }
+    }
+
+    fn is_valid_position(grid: &GridState, p: &Piece, pos: &Position) -> bool {
+        p.get_blocks(pos)
+            .iter()
+            .all(|block| !Game::is_occupied(grid, block.pos.grid_position()))
+    }
+
+    // walls and the floor count as occupied, the space above the grid doesn't
+    fn is_occupied(grid: &GridState, cell: GridPosition) -> bool {
+        if cell.x < 0 || cell.x as usize >= COLUMNS || cell.y >= ROWS as i32 {
+            return true;
+        }
+        cell.y >= 0 && grid.0[cell.y as usize][cell.x as usize].is_some()
+    }
+
+    // rotates the piece in place if any of the kick tests fit, returning where
+    // it ended up and which test it was. otherwise the piece is left untouched.
+    fn try_rotation(
+        grid: &GridState,
+        p: &mut Piece,
+        pos: &Position,
+        to: Direction,
+    ) -> Option<(Position, usize)> {
+        let from = p.facing;
+        let occupied = |cell| Game::is_occupied(grid, cell);
+        let kicks = p.rotation.system().kicks(p, pos, to, &occupied);
+        p.facing = to;
+        for (i, kick) in kicks.into_iter().enumerate() {
+            let kicked = pos.shift(kick);
+            if Game::is_valid_position(grid, p, &kicked) {
+                return Some((kicked, i));
+            }
+        }
+        p.facing = from;
+        None
+    }
+
+    // moves the piece down by `rows` one row at a time, so that even at 20G it
+    // can't skip over anything, and stops on the first row that's blocked
+    fn fall(grid: &GridState, p: &Piece, pos: Position, rows: f32) -> Position {
+        let start = pos.absolute_position();
+        let target = Position::new(start.x, start.y + rows);
+        let mut row = pos.grid_position().y;
+        while row < target.grid_position().y {
+            let next = Position::new(start.x, (row + 1) as f32);
+            if !Game::is_valid_position(grid, p, &next) {
+                // at this point, we're resting on another block
+                return Position::new(start.x, row as f32);
+            }
+            row += 1;
+        }
+        target
+    }
+
+    fn update_piece_position(&mut self, input: &InputState, dt: f32) {
+        let pressed = input.pressed_since(&self.last_input);
+        let handling = self.options.handling;
+        let gravity = self.gravity();
+        let instant = gravity >= MAX_GRAVITY * FRAME_RATE;
+        let shift = self
+            .auto_shift
+            .update(&handling, input.left, input.right, dt);
+        self.last_input = input.clone();
+
+        if let Some(ref mut p) = self.falling {
+            let mut new_pos = p.pos;
+            let mut moved = false;
+
+            for _ in 0..shift.abs() {
+                let next = if shift < 0 {
+                    new_pos.move_left()
+                } else {
+                    new_pos.move_right()
+                };
+                if !Game::is_valid_position(&self.grid, p, &next) {
+                    break;
+                }
+                new_pos = next;
+                moved = true;
+                self.last_kick = None;
+                if instant {
+                    // at 20G the piece hugs the stack, so it drops into any
+                    // gap it slides over
+                    new_pos = Game::fall(&self.grid, p, new_pos, ROWS as f32);
+                }
+            }
+            let turns = [Input::RotateRight, Input::RotateLeft, Input::Rotate180];
+            for &turn in turns.iter().filter(|&&turn| pressed.is_down(turn)) {
+                let to = match turn {
+                    Input::RotateLeft => p.facing.ccw(),
+                    // none of the rotation systems have kicks for a half
+                    // turn, so it only ever happens in place
+                    Input::Rotate180 => p.facing.cw().cw(),
+                    _ => p.facing.cw(),
+                };
+                if let Some((kicked, kick)) = Game::try_rotation(&self.grid, p, &new_pos, to) {
+                    new_pos = kicked;
+                    moved = true;
+                    self.last_kick = Some(kick);
+                }
+            }
+            if pressed.up {
+                let mut down = new_pos.move_down();
+                let mut count = 0;
+                while Game::is_valid_position(&self.grid, p, &down) {
+                    count += 1;
+                    new_pos = down;
+                    down = new_pos.move_down();
+                }
+                p.landed = true;
+                self.score += count;
+                if count > 0 {
+                    self.last_kick = None;
+                }
+            }
+
+            let dy = if input.down {
+                // soft drop goes at least as fast as it would on level 1, so
+                // it still works with little or no gravity
+                let slowest = GravityKind::Guideline.curve().rows_per_second(1);
+                gravity.max(slowest) * handling.soft_drop_factor * dt
+            } else {
+                gravity * dt
+            };
+            let fallen = Game::fall(&self.grid, p, new_pos, dy);
+            if fallen.grid_position().y != new_pos.grid_position().y {
+                // falling a row means the last move wasn't a rotation anymore
+                self.last_kick = None;
+            }
+            p.pos = fallen;
+
+            if !p.landed {
+                let below = p.pos.shift(GridPosition::new(0, 1));
+                let grounded = !Game::is_valid_position(&self.grid, p, &below);
+                p.landed = self.lock.update(
+                    self.options.lock_reset,
+                    self.options.lock_delay,
+                    p.pos.grid_position().y,
+                    grounded,
+                    moved,
+                    dt,
+                );
+            }
+        }
+        if matches!(self.falling, Some(ref p) if p.landed) {
+            let p = self.falling.take().unwrap();
+            self.lock_piece(&p);
+        }
+    }
+
+    fn compute_projection_position(grid: &GridState, p: &Piece) -> Position {
+        let mut down = p.pos;
+        let mut new_pos = down;
+        while Game::is_valid_position(grid, p, &down) {
+            new_pos = down;
+            down = down.move_down();
+        }
+        new_pos
+    }
+
+    fn update_projection(&mut self) {
+        if let Some(ref p) = self.falling {
+            let mut proj = p.clone();
+            proj.pos = Game::compute_projection_position(&self.grid, &proj);
+            self.projection = Some(proj);
+        }
+    }
+}
+
+// every 10 lines is a level, except that starting further in means clearing
+// more lines before the first level up, the way the NES does it
+fn level_for_lines(start_level: u32, lines: u32) -> u32 {
+    let start = start_level.max(1);
+    // the NES counts levels from 0
+    let nes_start = start - 1;
+    let first = (nes_start * 10 + 10).min(100.max((nes_start * 10).saturating_sub(50)));
+    if lines < first {
+        start
+    } else {
+        start + 1 + (lines - first) / 10
+    }
+}
+
+fn get_grid_idx(p: Position) -> Option<(usize, usize)> {
+    let pos = p.grid_position();
+    if pos.x < 0 || pos.x >= COLUMNS as i32 || pos.y < 0 || pos.y >= ROWS as i32 {
+        return None;
+    }
+    Some((pos.x as usize, pos.y as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::{COUNTDOWN, SOFT_DROP_FACTOR};
+    use crate::piece::Direction;
+
+    const DT: f32 = 1.0 / 60.0;
+    const SEED: u64 = 42;
+
+    #[test]
+    fn create_new_piece_calls_if_none() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        game.create_new_piece();
+        assert!(game.falling.is_some());
+    }
+
+    #[test]
+    fn no_falling_piece_at_start() {
+        let game = Game::new(SEED, &GameOptions::default());
+        assert!(game.falling.is_none());
+    }
+
+    #[test]
+    fn piece_position_falling() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        game.create_new_piece();
+        let initial_pos = game.falling.as_ref().unwrap().pos;
+        game.update_piece_position(&InputState::default(), DT);
+        let p = game.falling.as_ref().unwrap();
+
+        assert_eq!(
+            p.pos.absolute_position().y,
+            initial_pos.absolute_position().y + game.gravity() * DT
+        );
+        assert_eq!(
+            p.pos.absolute_position().x,
+            initial_pos.absolute_position().x
+        );
+    }
+
+    #[test]
+    fn piece_position_left_input() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        game.create_new_piece();
+        let initial_pos = game.falling.as_ref().unwrap().pos;
+        let input = InputState {
+            left: true,
+            ..InputState::default()
+        };
+        game.update_piece_position(&input, DT);
+        let p = game.falling.as_ref().unwrap();
+
+        assert_eq!(
+            p.pos.absolute_position().y,
+            initial_pos.absolute_position().y + game.gravity() * DT
+        );
+        assert_eq!(
+            p.pos.absolute_position().x,
+            initial_pos.absolute_position().x - 1.0
+        );
+    }
+
+    #[test]
+    fn piece_position_right_input() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        game.create_new_piece();
+        let initial_pos = game.falling.as_ref().unwrap().pos;
+        let input = InputState {
+            right: true,
+            ..InputState::default()
+        };
+        game.update_piece_position(&input, DT);
+        let p = game.falling.as_ref().unwrap();
+
+        assert_eq!(
+            p.pos.absolute_position().y,
+            initial_pos.absolute_position().y + game.gravity() * DT
+        );
+        assert_eq!(
+            p.pos.absolute_position().x,
+            initial_pos.absolute_position().x + 1.0
+        );
+    }
+
+    #[test]
+    fn piece_position_soft_down_input() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        game.create_new_piece();
+        let initial_pos = game.falling.as_ref().unwrap().pos;
+        let input = InputState {
+            down: true,
+            ..InputState::default()
+        };
+        game.update_piece_position(&input, DT);
+        let p = game.falling.as_ref().unwrap();
+
+        assert_eq!(
+            p.pos.absolute_position().y,
+            initial_pos.absolute_position().y + game.gravity() * SOFT_DROP_FACTOR * DT
+        );
+        assert_eq!(
+            p.pos.absolute_position().x,
+            initial_pos.absolute_position().x
+        );
+    }
+
+    #[test]
+    fn level_up_speeds_up_the_falling_piece() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        game.create_new_piece();
+        let slow = game.gravity();
+        game.level = 10;
+        assert!(game.gravity() > slow);
+        let initial_pos = game.falling.as_ref().unwrap().pos;
+        game.update_piece_position(&InputState::default(), DT);
+        let p = game.falling.as_ref().unwrap();
+        assert_eq!(
+            p.pos.absolute_position().y,
+            initial_pos.absolute_position().y + game.gravity() * DT
+        );
+    }
+
+    fn master_game() -> Game {
+        let options = GameOptions {
+            gravity: GravityKind::Master,
+            ..GameOptions::default()
+        };
+        Game::new(SEED, &options)
+    }
+
+    #[test]
+    fn twenty_g_lands_on_the_stack_without_tunneling() {
+        let mut game = master_game();
+        // a one row thick shelf with empty space underneath
+        fill(
+            &mut game,
+            &(0..COLUMNS).map(|x| (x, 12)).collect::<Vec<_>>(),
+        );
+        game.falling = Some(piece_at(PieceType::OShape, 4., 1.));
+        game.update_piece_position(&InputState::default(), DT);
+        let p = game.falling.as_ref().unwrap();
+        assert_eq!(p.pos.grid_position().y, 11);
+    }
+
+    #[test]
+    fn twenty_g_drops_into_gaps_while_sliding() {
+        let mut game = master_game();
+        let bottom = ROWS - 1;
+        fill(
+            &mut game,
+            &(0..COLUMNS)
+                .filter(|&x| x != 2 && x != 3)
+                .map(|x| (x, bottom))
+                .collect::<Vec<_>>(),
+        );
+        game.falling = Some(piece_at(PieceType::OShape, 6., 1.));
+        // 20 rows in the first frame, then the last one
+        game.update_piece_position(&InputState::default(), DT);
+        game.update_piece_position(&InputState::default(), DT);
+        assert_eq!(
+            game.falling.as_ref().unwrap().pos.grid_position().y,
+            bottom as i32 - 1
+        );
+
+        // with an instant ARR, holding left runs into the gap, not over it
+        game.options.handling.arr = 0.0;
+        game.auto_shift = AutoShift::default();
+        let left = InputState {
+            left: true,
+            ..InputState::default()
+        };
+        let steps = (game.options.handling.das / DT).ceil() as usize + 1;
+        for _ in 0..steps {
+            game.update_piece_position(&left, DT);
+        }
+        let p = game.falling.as_ref().unwrap();
+        assert_eq!(p.pos.grid_position().x, 2);
+        assert_eq!(p.pos.grid_position().y, bottom as i32);
+    }
+
+    #[test]
+    fn later_start_levels_wait_longer_to_level_up() {
+        for lines in 0..40 {
+            assert_eq!(level_for_lines(1, lines), lines / 10 + 1);
+        }
+        assert_eq!(level_for_lines(6, 59), 6);
+        assert_eq!(level_for_lines(6, 60), 7);
+        // starting at NES level 9 or later takes 100 lines at least
+        assert_eq!(level_for_lines(10, 99), 10);
+        assert_eq!(level_for_lines(10, 100), 11);
+        assert_eq!(level_for_lines(10, 110), 12);
+        assert_eq!(level_for_lines(20, 139), 20);
+        assert_eq!(level_for_lines(20, 140), 21);
+    }
+
+    #[test]
+    fn marathon_with_a_goal_completes() {
+        let options = GameOptions {
+            mode: GameMode::Marathon(Some(150)),
+            start_level: 5,
+            ..GameOptions::default()
+        };
+        let mut game = Game::new(SEED, &options);
+        assert_eq!(game.level, 5);
+        assert_eq!(game.countdown(), 0.0);
+        game.lines_cleared = 149;
+        assert!(!game.is_complete());
+        game.lines_cleared = 150;
+        assert!(game.is_complete());
+    }
+
+    #[test]
+    fn dig_starts_with_garbage_and_ends_when_its_gone() {
+        let options = GameOptions {
+            mode: GameMode::Dig(5),
+            ..GameOptions::default()
+        };
+        let mut game = Game::new(SEED, &options);
+        assert_eq!(game.garbage_left, 5);
+        for row in game.grid.0.iter().skip(ROWS - 5) {
+            assert_eq!(row.iter().filter(|b| b.is_none()).count(), 1);
+        }
+        assert!(game.grid.0[ROWS - 6].iter().all(|b| b.is_none()));
+
+        // a full row above the garbage doesn't count
+        let above = ROWS - 6;
+        fill(
+            &mut game,
+            &(0..COLUMNS).map(|x| (x, above)).collect::<Vec<_>>(),
+        );
+        game.clear_full_rows(Spin::None);
+        assert_eq!(game.garbage_left, 5);
+
+        for y in ROWS - 4..ROWS {
+            fill(&mut game, &(0..COLUMNS).map(|x| (x, y)).collect::<Vec<_>>());
+        }
+        game.clear_full_rows(Spin::None);
+        assert_eq!(game.garbage_left, 1);
+        assert!(!game.is_complete());
+
+        fill(
+            &mut game,
+            &(0..COLUMNS).map(|x| (x, ROWS - 1)).collect::<Vec<_>>(),
+        );
+        game.clear_full_rows(Spin::None);
+        assert_eq!(game.garbage_left, 0);
+        assert!(game.is_complete());
+    }
+
+    #[test]
+    fn the_clear_that_levels_up_scores_at_the_old_level() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        game.lines_cleared = 9;
+        let mut cells: Vec<_> = (0..COLUMNS).map(|x| (x, ROWS - 1)).collect();
+        // something left over, so it isn't a perfect clear
+        cells.push((0, ROWS - 2));
+        fill(&mut game, &cells);
+        game.clear_full_rows(Spin::None);
+        assert_eq!(game.level, 2);
+        assert_eq!(game.score, 100);
+    }
+
+    #[test]
+    fn same_seed_same_garbage() {
+        let options = GameOptions {
+            mode: GameMode::Dig(10),
+            messiness: 1.0,
+            ..GameOptions::default()
+        };
+        let a = Game::new(SEED, &options);
+        let b = Game::new(SEED, &options);
+        assert_eq!(a.grid.to_string(), b.grid.to_string());
+        // the garbage doesn't change which pieces come
+        let plain = Game::new(SEED, &GameOptions::default());
+        let a_next: Vec<_> = a.next.iter().map(|p| p.piece_type).collect();
+        let plain_next: Vec<_> = plain.next.iter().map(|p| p.piece_type).collect();
+        assert_eq!(a_next, plain_next);
+    }
+
+    #[test]
+    fn simulated_games_end_without_input() {
+        for seed in 0..3 {
+            let mut game = Game::new(seed, &GameOptions::default());
+            let input = InputState::default();
+            let mut steps = 0;
+            while game.top_out.is_none() {
+                game.step(&input, DT);
+                steps += 1;
+                assert!(steps < 100_000, "game never ended");
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_pieces() {
+        let mut a = Game::new(SEED, &GameOptions::default());
+        let mut b = Game::new(SEED, &GameOptions::default());
+        let input = InputState::default();
+        for _ in 0..2000 {
+            a.step(&input, DT);
+            b.step(&input, DT);
+            assert_eq!(
+                a.falling.as_ref().map(|p| p.piece_type),
+                b.falling.as_ref().map(|p| p.piece_type)
+            );
+        }
+        let a_next: Vec<_> = a.next.iter().map(|p| p.piece_type).collect();
+        let b_next: Vec<_> = b.next.iter().map(|p| p.piece_type).collect();
+        assert_eq!(a_next, b_next);
+        assert_eq!(a.seed(), SEED);
+    }
+
+    #[test]
+    fn sprint_waits_for_the_countdown_and_ends_at_the_goal() {
+        let options = GameOptions {
+            mode: GameMode::Sprint(20),
+            ..GameOptions::default()
+        };
+        let mut game = Game::new(SEED, &options);
+        let input = InputState::default();
+        let mut steps = 0;
+        while game.countdown() > 0.0 {
+            game.step(&input, DT);
+            assert!(game.falling.is_none());
+            steps += 1;
+        }
+        let expected = (COUNTDOWN / DT).round() as usize;
+        assert!((expected..=expected + 1).contains(&steps));
+        game.step(&input, DT);
+        assert!(game.falling.is_some());
+        assert_eq!(game.time, DT);
+
+        game.lines_cleared = 20;
+        assert!(game.is_complete());
+        let before = game.falling.as_ref().unwrap().pos;
+        game.step(&input, DT);
+        assert_eq!(game.time, DT);
+        assert_eq!(game.falling.as_ref().unwrap().pos, before);
+    }
+
+    #[test]
+    fn ultra_ends_when_time_runs_out() {
+        let options = GameOptions {
+            mode: GameMode::Ultra(120),
+            ..GameOptions::default()
+        };
+        let mut game = Game::new(SEED, &options);
+        game.countdown = 0.0;
+        game.time = 120.0 - DT / 2.0;
+        game.step(&InputState::default(), DT);
+        assert!(game.is_complete());
+        assert_eq!(game.time, 120.0);
+        game.lines_cleared = 1000;
+        game.step(&InputState::default(), DT);
+        assert_eq!(game.time, 120.0);
+    }
+
+    #[test]
+    fn marathon_starts_right_away_and_never_completes() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        assert_eq!(game.countdown(), 0.0);
+        game.step(&InputState::default(), DT);
+        assert!(game.falling.is_some());
+        game.lines_cleared = 1000;
+        assert!(!game.is_complete());
+    }
+
+    #[test]
+    fn locking_counts_pieces() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        let drop = InputState {
+            up: true,
+            ..InputState::default()
+        };
+        for _ in 0..3 {
+            game.step(&InputState::default(), DT);
+            game.step(&drop, DT);
+        }
+        assert_eq!(game.pieces, 3);
+        assert!(game.pieces_per_second() > 0.0);
+    }
+
+    fn piece_at(piece_type: PieceType, x: f32, y: f32) -> Piece {
+        let mut p = Piece::new(piece_type, RotationKind::Srs);
+        p.pos = Position::new(x, y);
+        p
+    }
+
+    fn fill(game: &mut Game, cells: &[(usize, usize)]) {
+        for &(x, y) in cells {
+            game.grid.0[y][x] = Some(Block {
+                piece_type: PieceType::OShape,
+                pos: Position::new(x as f32, y as f32),
+            });
+        }
+    }
+
+    fn grid_positions(p: &Piece) -> Vec<(i32, i32)> {
+        let mut cells: Vec<_> = p
+            .get_blocks(&p.pos)
+            .iter()
+            .map(|b| (b.pos.grid_position().x, b.pos.grid_position().y))
+            .collect();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn i_piece_rotates_around_its_center() {
+        let game = Game::new(SEED, &GameOptions::default());
+        let mut p = piece_at(PieceType::IShape, 4., 10.);
+        let pos = p.pos;
+        let (kicked, _) = Game::try_rotation(&game.grid, &mut p, &pos, Direction::East).unwrap();
+        p.pos = kicked;
+        assert_eq!(p.facing, Direction::East);
+        assert_eq!(grid_positions(&p), vec![(5, 9), (5, 10), (5, 11), (5, 12)]);
+    }
+
+    #[test]
+    fn rotation_kicks_off_the_wall() {
+        let game = Game::new(SEED, &GameOptions::default());
+        // T pointing right, flush against the left wall
+        let mut p = piece_at(PieceType::TShape, 0., 10.);
+        p.facing = Direction::East;
+        let pos = p.pos;
+        assert!(Game::is_valid_position(&game.grid, &p, &pos));
+        let (kicked, _) = Game::try_rotation(&game.grid, &mut p, &pos, Direction::North).unwrap();
+        assert_eq!(p.facing, Direction::North);
+        assert_eq!(kicked.grid_position().x, 1);
+        assert_eq!(kicked.grid_position().y, 10);
+    }
+
+    #[test]
+    fn rotation_kicks_off_the_floor() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        // I lying flat on a stack can stand up by kicking upwards
+        let bottom = ROWS - 1;
+        fill(
+            &mut game,
+            &(0..COLUMNS)
+                .filter(|&x| x != 4)
+                .map(|x| (x, bottom))
+                .collect::<Vec<_>>(),
+        );
+        let mut p = piece_at(PieceType::IShape, 4., (bottom - 1) as f32);
+        let pos = p.pos;
+        let kicked = Game::try_rotation(&game.grid, &mut p, &pos, Direction::East);
+        assert!(kicked.is_some());
+        assert_eq!(p.facing, Direction::East);
+        assert!(Game::is_valid_position(&game.grid, &p, &kicked.unwrap().0));
+    }
+
+    #[test]
+    fn failed_rotation_leaves_piece_alone() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        let cells: Vec<_> = (0..COLUMNS)
+            .flat_map(|x| (0..ROWS).map(move |y| (x, y)))
+            .filter(|&(x, y)| !((y == 10 && (3..=5).contains(&x)) || (x == 4 && y == 9)))
+            .collect();
+        fill(&mut game, &cells);
+        let mut p = piece_at(PieceType::TShape, 4., 10.);
+        let pos = p.pos;
+        assert!(Game::try_rotation(&game.grid, &mut p, &pos, Direction::East).is_none());
+        assert_eq!(p.facing, Direction::North);
+    }
+
+    #[test]
+    fn half_turns_rotate_in_place() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        let p = piece_at(PieceType::TShape, 4., 10.);
+        let pos = p.pos;
+        game.falling = Some(p);
+        let rotate_180 = InputState {
+            rotate_180: true,
+            ..InputState::default()
+        };
+        game.step(&rotate_180, DT);
+        let p = game.falling.clone().unwrap();
+        assert_eq!(p.facing, Direction::South);
+        assert_eq!(p.pos.grid_position(), pos.grid_position());
+        // holding it down doesn't keep turning
+        game.step(&rotate_180, DT);
+        assert_eq!(game.falling.as_ref().unwrap().facing, Direction::South);
+    }
+
+    #[test]
+    fn t_spin_double_is_scored_and_announced() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        let bottom = ROWS - 1;
+        let mut cells: Vec<_> = (0..COLUMNS)
+            .filter(|&x| x != 4)
+            .map(|x| (x, bottom))
+            .chain(
+                (0..COLUMNS)
+                    .filter(|x| !(3..=5).contains(x))
+                    .map(|x| (x, bottom - 1)),
+            )
+            .collect();
+        cells.push((3, bottom - 2));
+        fill(&mut game, &cells);
+        let mut p = piece_at(PieceType::TShape, 4., (bottom - 1) as f32);
+        p.facing = Direction::East;
+        game.falling = Some(p);
+
+        game.step(
+            &InputState {
+                rotate_right: true,
+                ..InputState::default()
+            },
+            DT,
+        );
+        game.step(
+            &InputState {
+                up: true,
+                ..InputState::default()
+            },
+            DT,
+        );
+        let events = game.take_events();
+        assert_eq!(
+            events,
+            vec![GameEvent::LineClear(Clear {
+                lines: 2,
+                spin: Spin::Full,
+                combo: 0,
+                back_to_back: false,
+                perfect: false,
+                points: 1200,
+            })]
+        );
+        assert_eq!(events[0].name().unwrap(), "T-SPIN DOUBLE");
+        assert_eq!(game.score, 1200);
+        assert!(game.take_events().is_empty());
+    }
+
+    #[test]
+    fn emptying_the_board_is_a_perfect_clear() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        let bottom = ROWS - 1;
+        fill(
+            &mut game,
+            &(4..COLUMNS).map(|x| (x, bottom)).collect::<Vec<_>>(),
+        );
+        game.falling = Some(piece_at(PieceType::IShape, 1., 5.));
+        game.step(
+            &InputState {
+                up: true,
+                ..InputState::default()
+            },
+            DT,
+        );
+        let events = game.take_events();
+        match events.as_slice() {
+            [GameEvent::LineClear(clear)] => assert!(clear.perfect),
+            _ => panic!("expected one clear, got {:?}", events),
+        }
+        assert_eq!(game.chain.perfect_clears, 1);
+        assert_eq!(game.chain.combo, Some(0));
+        assert!(game.grid.0.iter().flatten().all(|b| b.is_none()));
+    }
+
+    #[test]
+    fn piece_waits_for_lock_delay_on_the_ground() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        game.falling = Some(piece_at(PieceType::OShape, 4., (ROWS - 1) as f32));
+        let input = InputState::default();
+        let steps = (LOCK_DELAY / DT).ceil() as usize;
+        for _ in 0..steps - 1 {
+            game.update_piece_position(&input, DT);
+            assert!(game.falling.is_some());
+        }
+        assert!(game.lock_progress() > 0.9);
+        game.update_piece_position(&input, DT);
+        assert!(game.falling.is_none());
+        assert!(game.grid.0[ROWS - 1][4].is_some());
+    }
+
+    #[test]
+    fn hold_swaps_once_per_piece() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        let hold = InputState {
+            hold: true,
+            ..InputState::default()
+        };
+        let released = InputState::default();
+        game.step(&released, DT);
+        let first = game.falling.as_ref().unwrap().piece_type;
+        let second = game.next[0].piece_type;
+
+        game.step(&hold, DT);
+        assert_eq!(game.held.as_ref().unwrap().piece_type, first);
+        assert_eq!(game.falling.as_ref().unwrap().piece_type, second);
+        assert!(!game.can_hold());
+
+        // pressing again doesn't swap back until the piece locks
+        game.step(&released, DT);
+        game.step(&hold, DT);
+        assert_eq!(game.held.as_ref().unwrap().piece_type, first);
+        assert_eq!(game.falling.as_ref().unwrap().piece_type, second);
+
+        let drop = InputState {
+            up: true,
+            ..InputState::default()
+        };
+        game.step(&released, DT);
+        game.step(&drop, DT);
+        assert!(game.can_hold());
+    }
+
+    #[test]
+    fn held_piece_comes_back_at_spawn() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        game.step(&InputState::default(), DT);
+        let spawn = game.falling.clone().unwrap();
+        {
+            let p = game.falling.as_mut().unwrap();
+            p.pos = Position::new(0., 12.);
+            p.facing = Direction::West;
+        }
+        game.hold_used = false;
+        game.held = Some(piece_at(PieceType::IShape, 7., 15.));
+        game.swap_hold(&InputState {
+            hold: true,
+            ..InputState::default()
+        });
+        let falling = game.falling.as_ref().unwrap();
+        assert_eq!(falling.piece_type, PieceType::IShape);
+        assert_eq!(falling.pos, spawn.pos);
+        assert_eq!(falling.facing, Direction::North);
+        assert_eq!(game.held.as_ref().unwrap().facing, spawn.facing);
+    }
+
+    fn zen() -> GameOptions {
+        GameOptions {
+            mode: GameMode::Zen,
+            gravity: GravityKind::Zero,
+            ..GameOptions::default()
+        }
+    }
+
+    #[test]
+    fn zero_gravity_waits_for_a_drop() {
+        let mut game = Game::new(SEED, &zen());
+        game.step(&InputState::default(), DT);
+        let spawn = game.falling.as_ref().unwrap().pos;
+        for _ in 0..600 {
+            game.step(&InputState::default(), DT);
+        }
+        assert_eq!(game.falling.as_ref().unwrap().pos, spawn);
+        assert_eq!(game.pieces, 0);
+    }
+
+    #[test]
+    fn soft_drop_works_without_gravity() {
+        let mut game = Game::new(SEED, &zen());
+        game.step(&InputState::default(), DT);
+        let spawn = game.falling.as_ref().unwrap().pos.grid_position().y;
+        let down = InputState {
+            down: true,
+            ..InputState::default()
+        };
+        for _ in 0..10 {
+            game.step(&down, DT);
+        }
+        let y = game.falling.as_ref().unwrap().pos.grid_position().y;
+        assert!(y > spawn + 1, "{} to {}", spawn, y);
+        assert_eq!(game.pieces, 0);
+    }
+
+    #[test]
+    fn undo_takes_back_the_last_piece() {
+        let mut game = Game::new(SEED, &zen());
+        let released = InputState::default();
+        let drop = InputState {
+            up: true,
+            ..InputState::default()
+        };
+        let undo = InputState {
+            undo: true,
+            ..InputState::default()
+        };
+        game.step(&released, DT);
+        let first = game.falling.as_ref().unwrap().piece_type;
+        game.step(&drop, DT);
+        game.step(&released, DT);
+        let second = game.falling.as_ref().unwrap().piece_type;
+        game.step(&drop, DT);
+        game.step(&released, DT);
+        assert_eq!(game.pieces, 2);
+        assert_eq!(game.undo_count(), 2);
+
+        game.step(&undo, DT);
+        assert_eq!(game.pieces, 1);
+        assert_eq!(game.falling.as_ref().unwrap().piece_type, second);
+        game.step(&released, DT);
+        game.step(&undo, DT);
+        assert_eq!(game.pieces, 0);
+        assert_eq!(game.score, 0);
+        assert_eq!(game.falling.as_ref().unwrap().piece_type, first);
+        assert!(game.grid.0.iter().flatten().all(|b| b.is_none()));
+        assert!(!game.undo());
+
+        // the queue comes back the same way it went
+        game.step(&drop, DT);
+        game.step(&released, DT);
+        assert_eq!(game.falling.as_ref().unwrap().piece_type, second);
+    }
+
+    #[test]
+    fn undo_is_only_for_practice() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        game.step(&InputState::default(), DT);
+        game.step(
+            &InputState {
+                up: true,
+                ..InputState::default()
+            },
+            DT,
+        );
+        game.step(
+            &InputState {
+                undo: true,
+                ..InputState::default()
+            },
+            DT,
+        );
+        assert_eq!(game.pieces, 1);
+    }
+
+    #[test]
+    fn zen_clears_the_board_instead_of_topping_out() {
+        let mut game = Game::new(SEED, &zen());
+        let spawn = BUFFER_ROWS - 1;
+        game.grid.0[spawn][4] = Some(Block {
+            piece_type: PieceType::OShape,
+            pos: Position::new(4., spawn as f32),
+        });
+        game.step(&InputState::default(), DT);
+        assert!(game.top_out.is_none());
+        assert!(game.grid.0.iter().flatten().all(|b| b.is_none()));
+        assert!(game.falling.is_some());
+    }
+
+    #[test]
+    fn spawning_into_the_stack_is_a_block_out() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        let spawn = BUFFER_ROWS - 1;
+        game.grid.0[spawn][4] = Some(Block {
+            piece_type: PieceType::OShape,
+            pos: Position::new(4., spawn as f32),
+        });
+        game.step(&InputState::default(), DT);
+        assert_eq!(game.top_out, Some(TopOut::Block));
+        // nothing moves once the game is over
+        let pos = game.falling.as_ref().unwrap().pos;
+        game.step(&InputState::default(), DT);
+        assert_eq!(game.falling.as_ref().unwrap().pos, pos);
+    }
+
+    #[test]
+    fn locking_above_the_field_is_a_lock_out() {
+        let mut game = Game::new(SEED, &GameOptions::default());
+        game.lock_piece(&piece_at(PieceType::OShape, 4., 1.));
+        assert_eq!(game.top_out, Some(TopOut::Lock));
+    }
+
+    #[test]
+    fn partial_lock_out_is_optional() {
+        // an O resting on the top visible row pokes one row into the buffer
+        let o = piece_at(PieceType::OShape, 4., BUFFER_ROWS as f32);
+        let mut game = Game::new(SEED, &GameOptions::default());
+        game.lock_piece(&o);
+        assert!(game.top_out.is_none());
+
+        let options = GameOptions {
+            partial_lock_out: true,
+            ..GameOptions::default()
+        };
+        let mut game = Game::new(SEED, &options);
+        game.lock_piece(&o);
+        assert_eq!(game.top_out, Some(TopOut::PartialLock));
+    }
+
+    #[test]
+    fn pieces_spawn_flat_side_down_and_centered() {
+        let top = BUFFER_ROWS as i32 - 1;
+        for &piece_type in PieceType::ALL.iter() {
+            let mut expected = match piece_type {
+                PieceType::IShape => vec![(3, top), (4, top), (5, top), (6, top)],
+                PieceType::OShape => vec![(4, top - 1), (5, top - 1), (4, top), (5, top)],
+                PieceType::TShape => vec![(4, top - 1), (3, top), (4, top), (5, top)],
+                PieceType::LShape => vec![(5, top - 1), (3, top), (4, top), (5, top)],
+                PieceType::LShapeInverted => vec![(3, top - 1), (3, top), (4, top), (5, top)],
+                PieceType::RShape => vec![(3, top - 1), (4, top - 1), (4, top), (5, top)],
+                PieceType::RShapeInverted => {
+                    vec![(4, top - 1), (5, top - 1), (3, top), (4, top)]
+                }
+            };
+            expected.sort();
+            let p = Piece::new(piece_type, RotationKind::Srs);
+            assert_eq!(grid_positions(&p), expected, "{:?}", piece_type);
+        }
+    }
+
+    #[test]
+    fn every_rotation_system_spawns_in_the_buffer() {
+        for &rotation in RotationKind::ALL.iter() {
+            for &piece_type in PieceType::ALL.iter() {
+                let p = Piece::new(piece_type, rotation);
+                let cells = grid_positions(&p);
+                let left = cells.iter().map(|c| c.0).min().unwrap();
+                let right = cells.iter().map(|c| c.0).max().unwrap();
+                let bottom = cells.iter().map(|c| c.1).max().unwrap();
+                // centered, rounding left
+                assert_eq!(left, (COLUMNS as i32 - (right - left + 1)) / 2);
+                assert_eq!(bottom, BUFFER_ROWS as i32 - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn new_pieces_drop_into_the_field_if_they_can() {
+        for &piece_type in PieceType::ALL.iter() {
+            let mut game = Game::new(SEED, &GameOptions::default());
+            game.next[0] = Piece::new(piece_type, RotationKind::Srs);
+            game.create_new_piece();
+            let bottom = grid_positions(game.falling.as_ref().unwrap())
+                .iter()
+                .map(|c| c.1)
+                .max();
+            assert_eq!(bottom, Some(BUFFER_ROWS as i32), "{:?}", piece_type);
+
+            let mut game = Game::new(SEED, &GameOptions::default());
+            fill(
+                &mut game,
+                &(0..COLUMNS).map(|x| (x, BUFFER_ROWS)).collect::<Vec<_>>(),
+            );
+            game.next[0] = Piece::new(piece_type, RotationKind::Srs);
+            game.create_new_piece();
+            let bottom = grid_positions(game.falling.as_ref().unwrap())
+                .iter()
+                .map(|c| c.1)
+                .max();
+            assert_eq!(bottom, Some(BUFFER_ROWS as i32 - 1), "{:?}", piece_type);
+            game.step(&InputState::default(), DT);
+            assert_eq!(game.top_out, None);
+        }
+    }
+
+    #[test]
+    fn line_clear_delay_holds_the_rows_and_the_next_piece() {
+        let options = GameOptions {
+            line_clear_delay: 0.5,
+            ..GameOptions::default()
+        };
+        let mut game = Game::new(SEED, &options);
+        let bottom = ROWS - 1;
+        let cells: Vec<_> = (0..COLUMNS)
+            .filter(|&x| x != 4 && x != 5)
+            .flat_map(|x| vec![(x, bottom), (x, bottom - 1)])
+            .collect();
+        fill(&mut game, &cells);
+        game.lock_piece(&piece_at(PieceType::OShape, 4., bottom as f32));
+        // scored straight away, but the rows are still there
+        assert_eq!(game.lines_cleared, 2);
+        assert!(game.grid.0[bottom].iter().all(|b| b.is_some()));
+
+        let expected = (0.5 / DT) as u32;
+        let mut steps = 0;
+        while game.falling.is_none() {
+            assert!(game.grid.0[bottom].iter().all(|b| b.is_some()));
+            game.step(&InputState::default(), DT);
+            steps += 1;
+        }
+        assert!((expected..=expected + 1).contains(&steps), "{}", steps);
+        assert!(game.grid.0.iter().flatten().all(|b| b.is_none()));
+    }
+}
@@ -0,0 +1,53 @@
+use rand::Rng;
+use rand_pcg::Pcg32;
+
+use crate::constants::COLUMNS;
+
+// GARBAGE_STREAM keeps the garbage from using up numbers from the piece
+// sequence, so the same seed deals the same pieces with or without it
+pub const GARBAGE_STREAM: u64 = 0x6172_6261_6765;
+pub const MESSINESS_LEVELS: [f32; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+
+// the hole column of each row in a stack of garbage, from the bottom up. with
+// chance `messiness` a row's hole moves somewhere new, otherwise it lines up
+// with the hole below it.
+pub fn garbage_holes(rng: &mut Pcg32, rows: usize, messiness: f32) -> Vec<usize> {
+    let mut hole = rng.gen_range(0, COLUMNS);
+    let mut holes = Vec::with_capacity(rows);
+    for i in 0..rows {
+        if i > 0 && rng.gen::<f32>() < messiness {
+            // never the same column again, so a messy row can't line up by luck
+            hole = (hole + rng.gen_range(1, COLUMNS)) % COLUMNS;
+        }
+        holes.push(hole);
+    }
+    holes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn clean_garbage_lines_up() {
+        let mut rng = Pcg32::seed_from_u64(7);
+        let holes = garbage_holes(&mut rng, 10, 0.0);
+        assert_eq!(holes.len(), 10);
+        assert!(holes.iter().all(|&h| h == holes[0] && h < COLUMNS));
+    }
+
+    #[test]
+    fn fully_messy_garbage_always_moves() {
+        let mut rng = Pcg32::seed_from_u64(7);
+        let holes = garbage_holes(&mut rng, 50, 1.0);
+        assert!(holes.windows(2).all(|w| w[0] != w[1]));
+    }
+
+    #[test]
+    fn same_seed_same_garbage() {
+        let a = garbage_holes(&mut Pcg32::seed_from_u64(3), 20, 0.5);
+        let b = garbage_holes(&mut Pcg32::seed_from_u64(3), 20, 0.5);
+        assert_eq!(a, b);
+    }
+}
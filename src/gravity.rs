@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{FRAME_RATE, MAX_GRAVITY};
+
+// how fast pieces fall at each level, in rows per second. a G is a row per
+// frame, so 20G drops a piece through the whole field in a single frame.
+pub trait GravityCurve {
+    fn rows_per_second(&self, level: u32) -> f32;
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GravityKind {
+    #[default]
+    Guideline,
+    Nes,
+    Tgm,
+    Master,
+    Zero,
+}
+
+impl GravityKind {
+    pub const ALL: [GravityKind; 5] = [
+        GravityKind::Guideline,
+        GravityKind::Nes,
+        GravityKind::Tgm,
+        GravityKind::Master,
+        GravityKind::Zero,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GravityKind::Guideline => "Guideline",
+            GravityKind::Nes => "NES",
+            GravityKind::Tgm => "TGM",
+            GravityKind::Master => "20G",
+            GravityKind::Zero => "None",
+        }
+    }
+
+    pub fn curve(self) -> &'static dyn GravityCurve {
+        match self {
+            GravityKind::Guideline => &Guideline,
+            GravityKind::Nes => &Nes,
+            GravityKind::Tgm => &Tgm,
+            GravityKind::Master => &Master,
+            GravityKind::Zero => &Zero,
+        }
+    }
+}
+
+fn from_g(g: f32) -> f32 {
+    g.min(MAX_GRAVITY) * FRAME_RATE
+}
+
+// seconds per row is (0.8 - (level - 1) * 0.007) ^ (level - 1), which reaches
+// 20G at level 19
+pub struct Guideline;
+
+impl GravityCurve for Guideline {
+    fn rows_per_second(&self, level: u32) -> f32 {
+        let n = level.max(1) as f32 - 1.0;
+        let seconds_per_row = (0.8 - n * 0.007).max(0.0).powf(n);
+        from_g(1.0 / (seconds_per_row * FRAME_RATE))
+    }
+}
+
+// frames per row on the NTSC NES, starting from its level 0
+const NES_FRAMES: [u32; 30] = [
+    48, 43, 38, 33, 28, 23, 18, 13, 8, 6, 5, 5, 5, 4, 4, 4, 3, 3, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+    1,
+];
+const NES_FRAME_RATE: f32 = 60.0988;
+
+pub struct Nes;
+
+impl GravityCurve for Nes {
+    fn rows_per_second(&self, level: u32) -> f32 {
+        let index = (level.max(1) as usize - 1).min(NES_FRAMES.len() - 1);
+        NES_FRAME_RATE / NES_FRAMES[index] as f32
+    }
+}
+
+// TGM's internal gravity, in 1/256ths of a G, by the internal level it starts
+// at. that includes the slowdown back to crawling at 200.
+const TGM_GRAVITY: [(u32, u32); 30] = [
+    (0, 4),
+    (30, 6),
+    (35, 8),
+    (40, 10),
+    (50, 12),
+    (60, 16),
+    (70, 32),
+    (80, 48),
+    (90, 64),
+    (100, 80),
+    (120, 96),
+    (140, 112),
+    (160, 128),
+    (170, 144),
+    (200, 4),
+    (220, 32),
+    (230, 64),
+    (233, 96),
+    (236, 128),
+    (239, 160),
+    (243, 192),
+    (247, 224),
+    (251, 256),
+    (300, 512),
+    (330, 768),
+    (360, 1024),
+    (400, 1280),
+    (420, 1024),
+    (450, 768),
+    (500, 5120),
+];
+
+// TGM levels go up to 999 and move with every piece, so each of our levels
+// counts for this many of theirs, reaching 20G at level 21
+const TGM_LEVELS_PER_LEVEL: u32 = 25;
+
+pub struct Tgm;
+
+impl GravityCurve for Tgm {
+    fn rows_per_second(&self, level: u32) -> f32 {
+        let internal = (level.max(1) - 1) * TGM_LEVELS_PER_LEVEL;
+        let units = TGM_GRAVITY
+            .iter()
+            .take_while(|&&(start, _)| start <= internal)
+            .last()
+            .map_or(4, |&(_, units)| units);
+        from_g(units as f32 / 256.0)
+    }
+}
+
+// 20G from the first piece, for when the other curves are too easy
+pub struct Master;
+
+impl GravityCurve for Master {
+    fn rows_per_second(&self, _level: u32) -> f32 {
+        from_g(MAX_GRAVITY)
+    }
+}
+
+// pieces only come down when they're dropped, for practicing
+pub struct Zero;
+
+impl GravityCurve for Zero {
+    fn rows_per_second(&self, _level: u32) -> f32 {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guideline_starts_at_a_row_per_second() {
+        assert!((Guideline.rows_per_second(1) - 1.0).abs() < 1e-4);
+        // level 15 is about 0.007 seconds per row in the guideline
+        assert!((1.0 / Guideline.rows_per_second(15) - 0.00705).abs() < 1e-4);
+    }
+
+    #[test]
+    fn curves_top_out_at_20g() {
+        for kind in GravityKind::ALL.iter() {
+            let top = kind.curve().rows_per_second(100);
+            assert!(top <= MAX_GRAVITY * FRAME_RATE);
+        }
+        assert_eq!(Guideline.rows_per_second(20), MAX_GRAVITY * FRAME_RATE);
+        assert_eq!(Tgm.rows_per_second(21), MAX_GRAVITY * FRAME_RATE);
+    }
+
+    #[test]
+    fn nes_follows_the_frame_table() {
+        assert!((Nes.rows_per_second(1) - NES_FRAME_RATE / 48.0).abs() < 1e-4);
+        assert!((Nes.rows_per_second(30) - NES_FRAME_RATE).abs() < 1e-4);
+    }
+
+    #[test]
+    fn tgm_slows_down_again_at_200() {
+        // internal level 175 and 200
+        assert!(Tgm.rows_per_second(8) > Tgm.rows_per_second(9));
+    }
+}
@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{ARR, COLUMNS, DAS, SOFT_DROP_FACTOR};
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Handling {
+    // seconds a direction has to be held before it starts repeating
+    pub das: f32,
+    // seconds between repeats once it does, 0 slides straight to the wall
+    pub arr: f32,
+    // how many times faster than gravity a soft drop falls
+    pub soft_drop_factor: f32,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Handling {
+            das: DAS,
+            arr: ARR,
+            soft_drop_factor: SOFT_DROP_FACTOR,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Shift {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Default, Clone)]
+struct ShiftTimer {
+    held: bool,
+    das: f32,
+    arr: f32,
+}
+
+impl ShiftTimer {
+    // returns how many cells this direction wants to move this step
+    fn update(&mut self, handling: &Handling, held: bool, dt: f32) -> i32 {
+        if !held {
+            *self = ShiftTimer::default();
+            return 0;
+        }
+        if !self.held {
+            *self = ShiftTimer {
+                held: true,
+                ..ShiftTimer::default()
+            };
+            return 1;
+        }
+
+        let charged = self.das >= handling.das;
+        self.das += dt;
+        if self.das < handling.das {
+            return 0;
+        }
+        if handling.arr <= 0.0 {
+            return COLUMNS as i32;
+        }
+
+        let mut cells = 0;
+        if charged {
+            self.arr += dt;
+        } else {
+            cells += 1;
+            self.arr = self.das - handling.das;
+        }
+        while self.arr >= handling.arr {
+            cells += 1;
+            self.arr -= handling.arr;
+        }
+        cells
+    }
+}
+
+// delayed auto shift for left and right. both directions keep their own
+// timers, and when both are held the one pressed last wins.
+#[derive(Debug, Default, Clone)]
+pub struct AutoShift {
+    left: ShiftTimer,
+    right: ShiftTimer,
+    active: Option<Shift>,
+}
+
+impl AutoShift {
+    // returns the number of cells to move this step, negative being left
+    pub fn update(&mut self, handling: &Handling, left: bool, right: bool, dt: f32) -> i32 {
+        if left && !self.left.held {
+            self.active = Some(Shift::Left);
+        } else if right && !self.right.held {
+            self.active = Some(Shift::Right);
+        }
+
+        let left_cells = self.left.update(handling, left, dt);
+        let right_cells = self.right.update(handling, right, dt);
+
+        self.active = match self.active {
+            Some(Shift::Left) if !left && right => Some(Shift::Right),
+            Some(Shift::Right) if !right && left => Some(Shift::Left),
+            _ if !left && !right => None,
+            active => active,
+        };
+
+        match self.active {
+            Some(Shift::Left) => -left_cells,
+            Some(Shift::Right) => right_cells,
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 64.0;
+
+    fn handling(das: f32, arr: f32) -> Handling {
+        Handling {
+            das,
+            arr,
+            soft_drop_factor: SOFT_DROP_FACTOR,
+        }
+    }
+
+    #[test]
+    fn tap_moves_once() {
+        let h = handling(0.25, 0.125);
+        let mut shift = AutoShift::default();
+        assert_eq!(shift.update(&h, true, false, DT), -1);
+        assert_eq!(shift.update(&h, true, false, DT), 0);
+        assert_eq!(shift.update(&h, false, false, DT), 0);
+        assert_eq!(shift.update(&h, true, false, DT), -1);
+    }
+
+    #[test]
+    fn repeats_after_das_at_arr() {
+        let h = handling(0.25, 0.125);
+        let mut shift = AutoShift::default();
+        let mut moves = vec![];
+        for _ in 0..64 {
+            moves.push(shift.update(&h, false, true, DT));
+        }
+        // one on press, one when das runs out, then one every arr for the
+        // remaining 0.75 seconds
+        assert_eq!(moves.iter().sum::<i32>(), 1 + 1 + 5);
+        assert_eq!(moves.iter().position(|&m| m == 1), Some(0));
+        assert!(moves[1..16].iter().all(|&m| m == 0));
+    }
+
+    #[test]
+    fn instant_arr_slides_to_the_wall() {
+        let h = handling(0.125, 0.0);
+        let mut shift = AutoShift::default();
+        assert_eq!(shift.update(&h, true, false, DT), -1);
+        let mut last = 0;
+        for _ in 0..8 {
+            last = shift.update(&h, true, false, DT);
+        }
+        assert_eq!(last, -(COLUMNS as i32));
+    }
+
+    #[test]
+    fn last_pressed_direction_wins() {
+        let h = handling(0.25, 0.125);
+        let mut shift = AutoShift::default();
+        assert_eq!(shift.update(&h, true, false, DT), -1);
+        assert_eq!(shift.update(&h, true, true, DT), 1);
+        assert_eq!(shift.update(&h, true, true, DT), 0);
+        // letting go of right hands control back to the still held left
+        for _ in 0..16 {
+            shift.update(&h, true, true, DT);
+        }
+        let moved: i32 = (0..8).map(|_| shift.update(&h, true, false, DT)).sum();
+        assert!(moved < 0);
+    }
+}
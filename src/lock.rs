@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+
+use crate::constants::LOCK_RESET_LIMIT;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value")]
+pub enum LockReset {
+    // successful moves and rotations restart the timer, up to a limit per piece
+    Move(u32),
+    // only falling to a new lowest row restarts the timer
+    Step,
+    // the timer keeps counting for as long as the piece is on the ground
+    Never,
+}
+
+impl LockReset {
+    pub const ALL: [LockReset; 3] = [
+        LockReset::Move(LOCK_RESET_LIMIT),
+        LockReset::Step,
+        LockReset::Never,
+    ];
+
+    pub fn name(self) -> String {
+        match self {
+            LockReset::Move(limit) => format!("{} Moves", limit),
+            LockReset::Step => "Step".to_string(),
+            LockReset::Never => "None".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct LockDelay {
+    elapsed: f32,
+    resets: u32,
+    lowest_row: Option<i32>,
+    grounded: bool,
+}
+
+impl LockDelay {
+    // advances the timer for one step, returning true once the piece should
+    // lock. `moved` is whether the piece successfully moved or rotated.
+    pub fn update(
+        &mut self,
+        policy: LockReset,
+        delay: f32,
+        row: i32,
+        grounded: bool,
+        moved: bool,
+        dt: f32,
+    ) -> bool {
+        let new_lowest = match self.lowest_row {
+            Some(lowest) => row > lowest,
+            None => true,
+        };
+        if new_lowest {
+            self.lowest_row = Some(row);
+        }
+        match policy {
+            LockReset::Move(limit) => {
+                if new_lowest {
+                    self.elapsed = 0.0;
+                    self.resets = 0;
+                } else if moved && (grounded || self.grounded) && self.resets < limit {
+                    // only moves on the ground count, or ones that just took
+                    // the piece off it, so taps on the way down don't use up
+                    // the limit
+                    self.elapsed = 0.0;
+                    self.resets += 1;
+                }
+            }
+            LockReset::Step => {
+                if new_lowest {
+                    self.elapsed = 0.0;
+                }
+            }
+            LockReset::Never => (),
+        }
+
+        self.grounded = grounded;
+        if grounded {
+            self.elapsed += dt;
+        }
+        grounded && self.elapsed >= delay
+    }
+
+    // how close the piece is to locking, from 0 to 1
+    pub fn progress(&self, delay: f32) -> f32 {
+        if !self.grounded {
+            return 0.0;
+        }
+        if delay <= 0.0 {
+            return 1.0;
+        }
+        (self.elapsed / delay).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 0.125;
+
+    #[test]
+    fn locks_after_delay_on_the_ground() {
+        let mut lock = LockDelay::default();
+        for _ in 0..3 {
+            assert!(!lock.update(LockReset::Move(15), 0.5, 10, true, false, DT));
+        }
+        assert!(lock.update(LockReset::Move(15), 0.5, 10, true, false, DT));
+    }
+
+    #[test]
+    fn does_not_count_in_the_air() {
+        let mut lock = LockDelay::default();
+        for _ in 0..20 {
+            assert!(!lock.update(LockReset::Never, 0.5, 10, false, false, DT));
+        }
+        assert_eq!(lock.progress(0.5), 0.0);
+    }
+
+    #[test]
+    fn move_reset_is_limited() {
+        let mut lock = LockDelay::default();
+        let policy = LockReset::Move(2);
+        lock.update(policy, 1.0, 10, true, false, DT);
+        for _ in 0..2 {
+            assert!(!lock.update(policy, 1.0, 10, true, false, 0.5));
+            assert!(!lock.update(policy, 1.0, 10, true, true, DT));
+        }
+        // out of resets, so moving no longer helps
+        assert!(!lock.update(policy, 1.0, 10, true, false, 0.75));
+        assert!(lock.update(policy, 1.0, 10, true, true, DT));
+    }
+
+    #[test]
+    fn moves_in_the_air_dont_use_up_resets() {
+        let mut lock = LockDelay::default();
+        let policy = LockReset::Move(1);
+        lock.update(policy, 1.0, 10, false, false, DT);
+        for _ in 0..5 {
+            lock.update(policy, 1.0, 10, false, true, DT);
+        }
+        // lands with its one reset still to spend
+        assert!(!lock.update(policy, 1.0, 10, true, false, 0.75));
+        assert!(!lock.update(policy, 1.0, 10, true, true, DT));
+        assert!(!lock.update(policy, 1.0, 10, true, false, 0.75));
+        assert!(lock.update(policy, 1.0, 10, true, true, 0.25));
+    }
+
+    #[test]
+    fn step_reset_ignores_moves() {
+        let mut lock = LockDelay::default();
+        lock.update(LockReset::Step, 0.5, 10, true, false, 0.375);
+        assert!(lock.update(LockReset::Step, 0.5, 10, true, true, DT));
+
+        let mut lock = LockDelay::default();
+        lock.update(LockReset::Step, 0.5, 10, true, false, 0.375);
+        assert!(!lock.update(LockReset::Step, 0.5, 11, true, false, DT));
+    }
+
+    #[test]
+    fn never_reset_keeps_counting() {
+        let mut lock = LockDelay::default();
+        lock.update(LockReset::Never, 0.5, 10, true, false, 0.375);
+        lock.update(LockReset::Never, 0.5, 11, false, true, DT);
+        assert!(lock.update(LockReset::Never, 0.5, 12, true, true, DT));
+    }
+}
@@ -1,24 +1,47 @@
 use std::env;
+use std::fs;
 use std::path;
 
 #[macro_use]
 extern crate gfx;
+extern crate directories;
 extern crate ggez;
 extern crate rand;
+extern crate rand_pcg;
+extern crate serde;
+extern crate toml;
 
+use directories::ProjectDirs;
 use ggez::conf;
 use ggez::event;
-use ggez::{ContextBuilder, GameResult};
+use ggez::{ContextBuilder, GameError, GameResult};
 
 mod assets;
+mod bindings;
 mod constants;
+mod engine;
+mod garbage;
+mod gravity;
+mod handling;
+mod lock;
+mod mode;
 mod piece;
 mod position;
+mod randomizer;
+mod replay;
+mod rotation;
+mod scores;
+mod scoring;
+mod settings;
 mod state;
 mod types;
 
+use crate::settings::Settings;
 use crate::state::{SceneManager};
 
+const GAME_ID: &str = "tetris";
+const AUTHOR: &str = "me";
+
 pub fn main() -> GameResult {
     let resource_dir = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
         let mut path = path::PathBuf::from(manifest_dir);
@@ -29,13 +52,48 @@ pub fn main() -> GameResult {
         path::PathBuf::from("./resources")
     };
 
-    let cb = ContextBuilder::new("tetris", "me")
-        .window_setup(conf::WindowSetup::default().title("Tetris"))
-        .window_mode(conf::WindowMode::default().dimensions(640., 480.).resizable(true))
+    let (settings, settings_loaded) = match load_settings() {
+        Ok(settings) => (settings, true),
+        Err(e) => {
+            println!("Couldn't load settings, using the defaults without saving: {}", e);
+            (Settings::default(), false)
+        }
+    };
+    let display = &settings.display;
+    let fullscreen = if display.fullscreen {
+        conf::FullscreenType::Desktop
+    } else {
+        conf::FullscreenType::Windowed
+    };
+
+    let cb = ContextBuilder::new(GAME_ID, AUTHOR)
+        .window_setup(conf::WindowSetup::default().title("Tetris").vsync(display.vsync))
+        .window_mode(
+            conf::WindowMode::default()
+                .dimensions(display.width, display.height)
+                .fullscreen_type(fullscreen)
+                .resizable(true),
+        )
         .add_resource_path(resource_dir);
 
     let (ctx, events_loop) = &mut cb.build()?;
 
-    let game = &mut SceneManager::new(ctx)?;
+    let game = &mut SceneManager::new(ctx, settings, settings_loaded)?;
     event::run(ctx, events_loop, game)
 }
+
+// the window can't be changed to match once it's open, so the settings are read
+// before ggez has made the context that knows where the user data directory is.
+// ggez puts it in the same place.
+fn load_settings() -> GameResult<Settings> {
+    let dirs = ProjectDirs::from("", AUTHOR, GAME_ID).ok_or_else(|| {
+        GameError::FilesystemError("No home directory to keep settings in".to_string())
+    })?;
+    let path = dirs.data_local_dir().join(Settings::FILE_NAME);
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+    let text = fs::read_to_string(&path)?;
+    Settings::parse(&text)
+        .map_err(|e| GameError::ConfigError(format!("{}: {}", path.display(), e)))
+}
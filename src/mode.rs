@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+
+use crate::constants::COUNTDOWN;
+
+// toml can't hold enum variants with values directly, so these get written out
+// as a table with the kind and its value
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "value")]
+pub enum GameMode {
+    // over after this many lines, or endless until the stack tops out
+    Marathon(Option<u32>),
+    // a race to clear this many lines
+    Sprint(u32),
+    // as many points as possible in this many seconds
+    Ultra(u32),
+    // a race to clear this many rows of garbage
+    Dig(u32),
+    // endless practice, where topping out clears the board and pieces can be
+    // taken back
+    Zen,
+}
+
+// written out by hand since #[default] only works on unit variants, and the
+// default is an endless marathon
+impl Default for GameMode {
+    fn default() -> Self {
+        GameMode::Marathon(None)
+    }
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 12] = [
+        GameMode::Marathon(None),
+        GameMode::Marathon(Some(150)),
+        GameMode::Marathon(Some(200)),
+        GameMode::Sprint(20),
+        GameMode::Sprint(40),
+        GameMode::Sprint(100),
+        GameMode::Ultra(120),
+        GameMode::Ultra(180),
+        GameMode::Dig(5),
+        GameMode::Dig(10),
+        GameMode::Dig(15),
+        GameMode::Zen,
+    ];
+
+    pub fn name(self) -> String {
+        match self {
+            GameMode::Marathon(None) => "Marathon".to_string(),
+            GameMode::Marathon(Some(lines)) => format!("Marathon {}", lines),
+            GameMode::Sprint(lines) => format!("Sprint {}", lines),
+            GameMode::Ultra(seconds) => format!("Ultra {}:{:02}", seconds / 60, seconds % 60),
+            GameMode::Dig(rows) => format!("Dig {}", rows),
+            GameMode::Zen => "Zen".to_string(),
+        }
+    }
+
+    // seconds of "3, 2, 1" before the first piece shows up
+    pub fn countdown(self) -> f32 {
+        match self {
+            GameMode::Marathon(_) | GameMode::Zen => 0.0,
+            GameMode::Sprint(_) | GameMode::Ultra(_) | GameMode::Dig(_) => COUNTDOWN,
+        }
+    }
+
+    pub fn line_goal(self) -> Option<u32> {
+        match self {
+            GameMode::Marathon(goal) => goal,
+            GameMode::Sprint(lines) => Some(lines),
+            GameMode::Ultra(_) | GameMode::Dig(_) | GameMode::Zen => None,
+        }
+    }
+
+    pub fn time_limit(self) -> Option<f32> {
+        match self {
+            GameMode::Ultra(seconds) => Some(seconds as f32),
+            _ => None,
+        }
+    }
+
+    pub fn garbage_rows(self) -> Option<u32> {
+        match self {
+            GameMode::Dig(rows) => Some(rows),
+            _ => None,
+        }
+    }
+
+    // whether the fastest finish wins rather than the highest score
+    pub fn is_race(self) -> bool {
+        match self {
+            GameMode::Sprint(_) | GameMode::Dig(_) => true,
+            GameMode::Marathon(_) | GameMode::Ultra(_) | GameMode::Zen => false,
+        }
+    }
+
+    // whether topping out clears the board and undo is allowed
+    pub fn is_practice(self) -> bool {
+        self == GameMode::Zen
+    }
+}
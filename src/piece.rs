@@ -1,8 +1,11 @@
-use std::f32::consts::{PI, FRAC_PI_2};
-use rand::{Rng};
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
 
+use crate::constants::{BUFFER_ROWS, COLUMNS};
 use crate::position::Position;
-use crate::types::{Rot2, Vec2, GridPosition};
+use crate::randomizer::{Randomizer, RandomizerKind};
+use crate::rotation::RotationKind;
+use crate::types::GridPosition;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum PieceType {
@@ -15,6 +18,18 @@ pub enum PieceType {
     TShape
 }
 
+impl PieceType {
+    pub const ALL: [PieceType; 7] = [
+        PieceType::IShape,
+        PieceType::LShape,
+        PieceType::LShapeInverted,
+        PieceType::RShape,
+        PieceType::RShapeInverted,
+        PieceType::OShape,
+        PieceType::TShape,
+    ];
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Direction {
     East,
@@ -23,6 +38,26 @@ pub enum Direction {
     North,
 }
 
+impl Direction {
+    pub fn cw(self) -> Direction {
+        match self {
+            Direction::East => Direction::South,
+            Direction::South => Direction::West,
+            Direction::West => Direction::North,
+            Direction::North => Direction::East,
+        }
+    }
+
+    pub fn ccw(self) -> Direction {
+        match self {
+            Direction::East => Direction::North,
+            Direction::South => Direction::East,
+            Direction::West => Direction::South,
+            Direction::North => Direction::West,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Block {
     pub piece_type: PieceType,
@@ -43,101 +78,75 @@ pub struct Piece {
     pub piece_type: PieceType,
     pub pos: Position,
     pub facing: Direction,
-    pub velocity: Vec2,
+    pub rotation: RotationKind,
     pub landed: bool,
-    block_positions: Vec<GridPosition>,
 }
 
 impl Piece {
-    pub fn rotate_cw(&mut self) {
-        self.facing = match self.piece_type {
-            PieceType::OShape => self.facing,
-            _ => {
-                match self.facing {
-                    Direction::East => Direction::South,
-                    Direction::South => Direction::West,
-                    Direction::West => Direction::North,
-                    Direction::North => Direction::East,
-                }
-            }
-
-        };
-    }
-
-    pub fn rotate_ccw(&mut self) {
-        self.facing = match self.piece_type {
-            PieceType::OShape => self.facing,
-            _ => {
-                match self.facing {
-                    Direction::East => Direction::North,
-                    Direction::South => Direction::East,
-                    Direction::West => Direction::South,
-                    Direction::North => Direction::West,
-                }
-            }
-
-        };
-    }
-
     pub fn block_position(&self, pos: &Position, rel_p: &GridPosition) -> Block {
-        let rotation = match self.facing {
-            Direction::East => Rot2::new(0.0),
-            Direction::South => Rot2::new(-1. * FRAC_PI_2),
-            Direction::West => Rot2::new(-1. * PI),
-            Direction::North => Rot2::new(-3. * FRAC_PI_2),
-        };
-
-        let piece_pos = pos.absolute_position();
-        let block_pos = Position::from_grid_position(*rel_p).absolute_position();
-        
         Block {
             piece_type: self.piece_type,
-            pos: Position::from_point(piece_pos + rotation * block_pos.coords)
+            pos: pos.shift(*rel_p),
         }
     }
 
     pub fn get_blocks(&self, pos: &Position) -> Vec<Block> {
-        self.block_positions.iter().map(|b_pos| {
-            self.block_position(pos, &b_pos)
-        }).collect()
+        self.rotation
+            .system()
+            .blocks(self.piece_type, self.facing)
+            .iter()
+            .map(|b_pos| self.block_position(pos, b_pos))
+            .collect()
     }
 }
 
-fn get_block_positions(piece_type: PieceType) -> Vec<GridPosition> {
-    match piece_type {
-        PieceType::IShape => vec![GridPosition::new(-1, 0), GridPosition::new(0, 0),GridPosition::new(1, 0), GridPosition::new(2, 0)],
-        PieceType::OShape => vec![GridPosition::new(0, 0), GridPosition::new(0, -1),GridPosition::new(1, 0), GridPosition::new(1, -1)],
-        PieceType::LShape => vec![GridPosition::new(-1, 0), GridPosition::new(0, 0),GridPosition::new(1, 0), GridPosition::new(1, -1)],
-        PieceType::LShapeInverted => vec![GridPosition::new(-1, 0), GridPosition::new(0, 0),GridPosition::new(1, 0), GridPosition::new(1, 1)],
-        PieceType::RShape => vec![GridPosition::new(1, 0), GridPosition::new(0, 0),GridPosition::new(0, -1), GridPosition::new(-1, -1)],
-        PieceType::RShapeInverted => vec![GridPosition::new(-1, 0), GridPosition::new(0, 0),GridPosition::new(0, -1), GridPosition::new(1, -1)],
-        PieceType::TShape => vec![GridPosition::new(-1, 0), GridPosition::new(0, 0),GridPosition::new(1, 0), GridPosition::new(0, -1)],
+impl Piece {
+    pub fn new(piece_type: PieceType, rotation: RotationKind) -> Piece {
+        let facing = rotation.system().spawn_facing(piece_type);
+        Piece {
+            piece_type,
+            pos: spawn_position(piece_type, rotation, facing),
+            facing,
+            rotation,
+            landed: false,
+        }
     }
 }
 
-pub fn create_random_piece(velocity: f32) -> Piece {
-    let piece_type: PieceType;
-    let mut rng = rand::thread_rng();
-    let r0: u32 = rng.gen_range(0, 7);
-    piece_type = match r0 {
-        0 => PieceType::IShape,
-        1 => PieceType::LShape,
-        2 => PieceType::LShapeInverted,
-        3 => PieceType::RShape,
-        4 => PieceType::RShapeInverted,
-        5 => PieceType::OShape,
-        6 => PieceType::TShape,
-        _ => panic!("generated a number out of range")
-    };
-
-    let p = Piece {
-        piece_type,
-        pos: Position::new(4., 1.),
-        facing: Direction::North,
-        landed: false,
-        velocity: Vec2::new(0., velocity),
-        block_positions: get_block_positions(piece_type),
-    };
-
-    p
+// centered in the field, rounding left, with the bottom of the piece on the
+// last of the hidden rows above the visible field
+fn spawn_position(piece_type: PieceType, rotation: RotationKind, facing: Direction) -> Position {
+    let blocks = rotation.system().blocks(piece_type, facing);
+    let left = blocks.iter().map(|b| b.x).min().unwrap();
+    let right = blocks.iter().map(|b| b.x).max().unwrap();
+    let bottom = blocks.iter().map(|b| b.y).max().unwrap();
+    let width = right - left + 1;
+    let x = (COLUMNS as i32 - width) / 2 - left;
+    let y = BUFFER_ROWS as i32 - 1 - bottom;
+    Position::new(x as f32, y as f32)
+}
+
+#[derive(Clone)]
+pub struct PieceGenerator {
+    seed: u64,
+    rng: Pcg32,
+    randomizer: Box<dyn Randomizer>,
+}
+
+impl PieceGenerator {
+    pub fn new(seed: u64, kind: RandomizerKind) -> PieceGenerator {
+        PieceGenerator {
+            seed,
+            rng: Pcg32::seed_from_u64(seed),
+            randomizer: kind.build(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_type(&mut self) -> PieceType {
+        self.randomizer.next_type(&mut self.rng)
+    }
 }
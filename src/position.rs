@@ -19,14 +19,6 @@ impl Position {
         }
     }
 
-    pub fn from_point(p: Point2) -> Position {
-        Position::new(p.x, p.y)
-    }
-
-    pub fn from_grid_position(p: GridPosition) -> Position {
-        Position::new(p.x as f32, p.y as f32)
-    }
-
     pub fn grid_position(&self) -> GridPosition {
         self.grid_pos
     }
@@ -47,6 +39,10 @@ impl Position {
         Position::new(self.xy.x, (self.xy.y + 1.0).floor())
     }
 
+    pub fn shift(&self, offset: GridPosition) -> Position {
+        Position::new(self.xy.x + offset.x as f32, self.xy.y + offset.y as f32)
+    }
+
     pub fn screen_coords(&self, block_size: f32, x_offset: f32, y_offset: f32) -> Point2 {
         let x = block_size * self.grid_pos.x as f32 + x_offset;
         let y = block_size * self.grid_pos.y as f32 + y_offset;
@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+
+use crate::piece::PieceType;
+
+pub trait Randomizer {
+    fn next_type(&mut self, rng: &mut Pcg32) -> PieceType;
+
+    // randomizers keep state between pieces, which game snapshots need a copy of
+    fn box_clone(&self) -> Box<dyn Randomizer>;
+}
+
+impl Clone for Box<dyn Randomizer> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RandomizerKind {
+    Classic,
+    #[default]
+    SevenBag,
+    History,
+}
+
+impl RandomizerKind {
+    pub const ALL: [RandomizerKind; 3] = [
+        RandomizerKind::SevenBag,
+        RandomizerKind::History,
+        RandomizerKind::Classic,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RandomizerKind::Classic => "Classic",
+            RandomizerKind::SevenBag => "7-Bag",
+            RandomizerKind::History => "TGM",
+        }
+    }
+
+    pub fn build(self) -> Box<dyn Randomizer> {
+        match self {
+            RandomizerKind::Classic => Box::new(ClassicRandomizer),
+            RandomizerKind::SevenBag => Box::new(BagRandomizer::new()),
+            RandomizerKind::History => Box::new(HistoryRandomizer::new()),
+        }
+    }
+}
+
+// every piece is equally likely on every draw, droughts and all
+#[derive(Clone)]
+pub struct ClassicRandomizer;
+
+impl Randomizer for ClassicRandomizer {
+    fn next_type(&mut self, rng: &mut Pcg32) -> PieceType {
+        PieceType::ALL[rng.gen_range(0, PieceType::ALL.len())]
+    }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+// deals out a shuffled set of all seven pieces before starting a new one
+#[derive(Clone, Default)]
+pub struct BagRandomizer {
+    bag: Vec<PieceType>,
+}
+
+impl BagRandomizer {
+    pub fn new() -> Self {
+        BagRandomizer::default()
+    }
+}
+
+impl Randomizer for BagRandomizer {
+    fn next_type(&mut self, rng: &mut Pcg32) -> PieceType {
+        if self.bag.is_empty() {
+            self.bag.extend_from_slice(&PieceType::ALL);
+            self.bag.shuffle(rng);
+        }
+        self.bag.pop().unwrap()
+    }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+const HISTORY_ROLLS: u32 = 6;
+
+// rerolls pieces that were dealt recently, the first piece is never S, Z or O
+#[derive(Clone)]
+pub struct HistoryRandomizer {
+    history: VecDeque<PieceType>,
+    first: bool,
+}
+
+impl HistoryRandomizer {
+    pub fn new() -> Self {
+        HistoryRandomizer {
+            history: VecDeque::from(vec![
+                PieceType::RShape,
+                PieceType::RShapeInverted,
+                PieceType::RShapeInverted,
+                PieceType::RShape,
+            ]),
+            first: true,
+        }
+    }
+}
+
+impl Default for HistoryRandomizer {
+    fn default() -> Self {
+        HistoryRandomizer::new()
+    }
+}
+
+impl Randomizer for HistoryRandomizer {
+    fn next_type(&mut self, rng: &mut Pcg32) -> PieceType {
+        let piece_type = if self.first {
+            self.first = false;
+            let first = [
+                PieceType::IShape,
+                PieceType::LShape,
+                PieceType::LShapeInverted,
+                PieceType::TShape,
+            ];
+            first[rng.gen_range(0, first.len())]
+        } else {
+            let mut candidate = PieceType::ALL[rng.gen_range(0, PieceType::ALL.len())];
+            for _ in 1..HISTORY_ROLLS {
+                if !self.history.contains(&candidate) {
+                    break;
+                }
+                candidate = PieceType::ALL[rng.gen_range(0, PieceType::ALL.len())];
+            }
+            candidate
+        };
+        self.history.pop_front();
+        self.history.push_back(piece_type);
+        piece_type
+    }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn deal(kind: RandomizerKind, count: usize) -> Vec<PieceType> {
+        let mut rng = Pcg32::seed_from_u64(7);
+        let mut randomizer = kind.build();
+        (0..count).map(|_| randomizer.next_type(&mut rng)).collect()
+    }
+
+    #[test]
+    fn seven_bag_deals_every_piece_once_per_bag() {
+        let pieces = deal(RandomizerKind::SevenBag, 70);
+        for bag in pieces.chunks(7) {
+            for piece_type in PieceType::ALL.iter() {
+                assert_eq!(bag.iter().filter(|&p| p == piece_type).count(), 1);
+            }
+        }
+    }
+
+    #[test]
+    fn history_never_starts_with_s_z_or_o() {
+        for seed in 0..100 {
+            let mut rng = Pcg32::seed_from_u64(seed);
+            let first = HistoryRandomizer::new().next_type(&mut rng);
+            assert!(![
+                PieceType::RShape,
+                PieceType::RShapeInverted,
+                PieceType::OShape
+            ]
+            .contains(&first));
+        }
+    }
+
+    #[test]
+    fn history_repeats_less_than_classic() {
+        let repeats = |pieces: Vec<PieceType>| pieces.windows(2).filter(|w| w[0] == w[1]).count();
+        let classic = repeats(deal(RandomizerKind::Classic, 1000));
+        let history = repeats(deal(RandomizerKind::History, 1000));
+        assert!(history < classic);
+    }
+
+    #[test]
+    fn classic_deals_every_piece() {
+        let pieces = deal(RandomizerKind::Classic, 1000);
+        for piece_type in PieceType::ALL.iter() {
+            assert!(pieces.contains(piece_type));
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+
+use crate::engine::{Game, GameEvent, GameOptions, Input, InputState};
+
+// how fast a replay can be played back, as a multiple of real time
+pub const REPLAY_SPEEDS: [f32; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+// how far one press of seek jumps
+pub const SEEK_SECONDS: f32 = 5.0;
+
+// a button going down or coming back up, on the frame the game first saw it
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputEvent {
+    pub frame: u32,
+    pub input: Input,
+    pub down: bool,
+}
+
+// everything needed to play a game back exactly the way it went
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    // bumped whenever a change to the rules would make old replays play out
+    // differently
+    pub version: u32,
+    pub seed: u64,
+    // seconds each frame stepped the game by
+    pub dt: f32,
+    pub options: GameOptions,
+    // how many frames the game ran for
+    pub frames: u32,
+    pub events: Vec<InputEvent>,
+}
+
+impl Replay {
+    pub const VERSION: u32 = 1;
+    pub const DIRECTORY: &'static str = "replays";
+
+    pub fn new(seed: u64, dt: f32, options: &GameOptions) -> Self {
+        Replay {
+            version: Replay::VERSION,
+            seed,
+            dt,
+            options: options.clone(),
+            frames: 0,
+            events: Vec::new(),
+        }
+    }
+}
+
+// writes down what changed in the input every time the game is stepped
+pub struct Recorder {
+    replay: Replay,
+    input: InputState,
+}
+
+impl Recorder {
+    pub fn new(seed: u64, dt: f32, options: &GameOptions) -> Self {
+        Recorder {
+            replay: Replay::new(seed, dt, options),
+            input: InputState::default(),
+        }
+    }
+
+    // call once per frame with the input the game is about to be stepped with
+    pub fn record(&mut self, input: &InputState) {
+        let frame = self.replay.frames;
+        for &button in Input::ALL.iter() {
+            let down = input.is_down(button);
+            if down != self.input.is_down(button) {
+                self.replay.events.push(InputEvent {
+                    frame,
+                    input: button,
+                    down,
+                });
+            }
+        }
+        self.input = input.clone();
+        self.replay.frames += 1;
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+}
+
+// steps a fresh game through a replay's input, frame by frame
+pub struct Playback {
+    replay: Replay,
+    game: Game,
+    input: InputState,
+    frame: u32,
+    // index of the first event that hasn't happened yet
+    next_event: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        let game = Game::new(replay.seed, &replay.options);
+        Playback {
+            replay,
+            game,
+            input: InputState::default(),
+            frame: 0,
+            next_event: 0,
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn take_events(&mut self) -> Vec<GameEvent> {
+        self.game.take_events()
+    }
+
+    pub fn replay(&self) -> &Replay {
+        &self.replay
+    }
+
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.replay.frames
+    }
+
+    pub fn step(&mut self) {
+        if self.is_finished() {
+            return;
+        }
+        while let Some(event) = self.replay.events.get(self.next_event) {
+            if event.frame > self.frame {
+                break;
+            }
+            self.input.set(event.input, event.down);
+            self.next_event += 1;
+        }
+        self.game.step(&self.input, self.replay.dt);
+        self.frame += 1;
+    }
+
+    // going backwards means playing the game again from the start, which is
+    // quick since nothing gets drawn on the way
+    pub fn seek(&mut self, frame: u32) {
+        if frame < self.frame {
+            self.game = Game::new(self.replay.seed, &self.replay.options);
+            self.input = InputState::default();
+            self.frame = 0;
+            self.next_event = 0;
+        }
+        while self.frame < frame && !self.is_finished() {
+            self.step();
+            // announcements on the way are skipped over
+            self.game.take_events();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    // slides pieces to the left wall, the right wall or nowhere in turn, so
+    // they land all over the board
+    fn script(frame: u32) -> InputState {
+        let piece = frame / 40;
+        let mut input = InputState::default();
+        match (frame % 40, piece % 3) {
+            (0..=24, 0) => input.left = true,
+            (0..=24, 1) => input.right = true,
+            (25, _) => input.rotate_right = true,
+            (30, _) => input.up = true,
+            _ => (),
+        }
+        input
+    }
+
+    fn record(frames: u32) -> (Game, Replay) {
+        let options = GameOptions::default();
+        let mut game = Game::new(7, &options);
+        let mut recorder = Recorder::new(game.seed(), DT, &options);
+        for frame in 0..frames {
+            let input = script(frame);
+            recorder.record(&input);
+            game.step(&input, DT);
+        }
+        (game, recorder.replay().clone())
+    }
+
+    #[test]
+    fn playback_matches_the_recorded_game() {
+        let (game, replay) = record(3000);
+        assert!(game.pieces > 10);
+        let mut playback = Playback::new(replay);
+        while !playback.is_finished() {
+            playback.step();
+        }
+        let played = playback.game();
+        assert_eq!(played.grid.to_string(), game.grid.to_string());
+        assert_eq!(played.score, game.score);
+        assert_eq!(played.pieces, game.pieces);
+        assert_eq!(played.falling, game.falling);
+    }
+
+    #[test]
+    fn seeking_back_and_forth_lands_on_the_same_frame() {
+        let (_, replay) = record(1200);
+        let mut playback = Playback::new(replay.clone());
+        playback.seek(900);
+        let grid = playback.game().grid.to_string();
+        let score = playback.game().score;
+        playback.seek(300);
+        assert_eq!(playback.frame(), 300);
+        playback.seek(900);
+        assert_eq!(playback.game().grid.to_string(), grid);
+        assert_eq!(playback.game().score, score);
+        // seeking past the end stops at the end
+        playback.seek(5000);
+        assert_eq!(playback.frame(), replay.frames);
+    }
+
+    #[test]
+    fn replays_survive_a_round_trip_through_toml() {
+        let (_, mut replay) = record(200);
+        replay.options.mode = crate::mode::GameMode::Sprint(40);
+        let text = toml::to_string(&toml::Value::try_from(&replay).unwrap()).unwrap();
+        let loaded: Replay = toml::from_str(&text).unwrap();
+        assert_eq!(loaded, replay);
+    }
+}
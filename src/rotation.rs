@@ -0,0 +1,344 @@
+use serde::{Deserialize, Serialize};
+
+use crate::piece::{Direction, Piece, PieceType};
+use crate::position::Position;
+use crate::types::GridPosition;
+
+pub trait RotationSystem {
+    fn spawn_facing(&self, piece_type: PieceType) -> Direction;
+
+    // offsets of each block from the piece position, with y pointing down
+    fn blocks(&self, piece_type: PieceType, facing: Direction) -> [GridPosition; 4];
+
+    // translations to try in order when rotating `p` at `pos` to `to`. the
+    // first one that fits wins, and no kicks at all means the rotation fails.
+    fn kicks(
+        &self,
+        p: &Piece,
+        pos: &Position,
+        to: Direction,
+        occupied: &dyn Fn(GridPosition) -> bool,
+    ) -> Vec<GridPosition>;
+
+    // whether rotating with kick test `kick` makes a mini t-spin a full one.
+    // only SRS has a kick like that.
+    fn upgrades_t_spin(&self, _kick: usize) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RotationKind {
+    #[default]
+    Srs,
+    Ars,
+    Classic,
+}
+
+impl RotationKind {
+    pub const ALL: [RotationKind; 3] =
+        [RotationKind::Srs, RotationKind::Ars, RotationKind::Classic];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RotationKind::Srs => "SRS",
+            RotationKind::Ars => "ARS",
+            RotationKind::Classic => "Classic",
+        }
+    }
+
+    pub fn system(self) -> &'static dyn RotationSystem {
+        match self {
+            RotationKind::Srs => &Srs,
+            RotationKind::Ars => &Ars,
+            RotationKind::Classic => &Classic,
+        }
+    }
+}
+
+fn quarter_turns(facing: Direction) -> usize {
+    match facing {
+        Direction::North => 0,
+        Direction::East => 1,
+        Direction::South => 2,
+        Direction::West => 3,
+    }
+}
+
+// turns a shape clockwise around the block at (0, 0)
+fn rotate_blocks(mut blocks: [GridPosition; 4], facing: Direction) -> [GridPosition; 4] {
+    for _ in 0..quarter_turns(facing) {
+        for b in blocks.iter_mut() {
+            *b = GridPosition::new(-b.y, b.x);
+        }
+    }
+    blocks
+}
+
+// parses a 4x4 picture of a piece, measured from the block at row 1, column 1
+fn shape(rows: [&str; 4]) -> [GridPosition; 4] {
+    let mut blocks = [GridPosition::new(0, 0); 4];
+    let mut i = 0;
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            if c == '#' {
+                blocks[i] = GridPosition::new(x as i32 - 1, y as i32 - 1);
+                i += 1;
+            }
+        }
+    }
+    blocks
+}
+
+// the guideline Super Rotation System. J, L, S, T and Z rotate around one of
+// their blocks, I and O around the point between their middle blocks.
+pub struct Srs;
+
+impl RotationSystem for Srs {
+    fn spawn_facing(&self, _piece_type: PieceType) -> Direction {
+        Direction::North
+    }
+
+    fn blocks(&self, piece_type: PieceType, facing: Direction) -> [GridPosition; 4] {
+        let north = match piece_type {
+            PieceType::IShape => {
+                return match facing {
+                    Direction::North => shape(["....", "####", "....", "...."]),
+                    Direction::East => shape(["..#.", "..#.", "..#.", "..#."]),
+                    Direction::South => shape(["....", "....", "####", "...."]),
+                    Direction::West => shape([".#..", ".#..", ".#..", ".#.."]),
+                }
+            }
+            PieceType::OShape => return shape([".##.", ".##.", "....", "...."]),
+            PieceType::LShape => shape(["..#.", "###.", "....", "...."]),
+            PieceType::LShapeInverted => shape(["#...", "###.", "....", "...."]),
+            PieceType::RShape => shape(["##..", ".##.", "....", "...."]),
+            PieceType::RShapeInverted => shape([".##.", "##..", "....", "...."]),
+            PieceType::TShape => shape([".#..", "###.", "....", "...."]),
+        };
+        rotate_blocks(north, facing)
+    }
+
+    // the last and furthest of the five tests
+    fn upgrades_t_spin(&self, kick: usize) -> bool {
+        kick == 4
+    }
+
+    // wall kick tests straight from the guideline tables, which use y pointing
+    // up, so the sign is flipped on the way out
+    fn kicks(
+        &self,
+        p: &Piece,
+        _pos: &Position,
+        to: Direction,
+        _occupied: &dyn Fn(GridPosition) -> bool,
+    ) -> Vec<GridPosition> {
+        use Direction::*;
+        let tests: [(i32, i32); 5] = match p.piece_type {
+            PieceType::OShape => [(0, 0); 5],
+            PieceType::IShape => match (p.facing, to) {
+                (North, East) => [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+                (East, North) => [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+                (East, South) => [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+                (South, East) => [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+                (South, West) => [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+                (West, South) => [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+                (West, North) => [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+                (North, West) => [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+                _ => [(0, 0); 5],
+            },
+            _ => match (p.facing, to) {
+                (North, East) => [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+                (East, North) => [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+                (East, South) => [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+                (South, East) => [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+                (South, West) => [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+                (West, South) => [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+                (West, North) => [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+                (North, West) => [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+                _ => [(0, 0); 5],
+            },
+        };
+        tests
+            .iter()
+            .map(|&(x, y)| GridPosition::new(x, -y))
+            .collect()
+    }
+}
+
+// Arika's rotation from the TGM games. pieces sit at the bottom of their 3x3
+// box, spawn flat side up and only ever kick one column left or right.
+pub struct Ars;
+
+impl RotationSystem for Ars {
+    fn spawn_facing(&self, _piece_type: PieceType) -> Direction {
+        Direction::North
+    }
+
+    fn blocks(&self, piece_type: PieceType, facing: Direction) -> [GridPosition; 4] {
+        use Direction::*;
+        let rows = match (piece_type, facing) {
+            (PieceType::IShape, North) | (PieceType::IShape, South) => {
+                ["....", "####", "....", "...."]
+            }
+            (PieceType::IShape, _) => ["..#.", "..#.", "..#.", "..#."],
+            (PieceType::OShape, _) => ["....", ".##.", ".##.", "...."],
+            (PieceType::RShape, North) | (PieceType::RShape, South) => {
+                ["....", "##..", ".##.", "...."]
+            }
+            (PieceType::RShape, _) => ["..#.", ".##.", ".#..", "...."],
+            (PieceType::RShapeInverted, North) | (PieceType::RShapeInverted, South) => {
+                ["....", ".##.", "##..", "...."]
+            }
+            (PieceType::RShapeInverted, _) => ["#...", "##..", ".#..", "...."],
+            (PieceType::TShape, North) => ["....", "###.", ".#..", "...."],
+            (PieceType::TShape, East) => [".#..", "##..", ".#..", "...."],
+            (PieceType::TShape, South) => ["....", ".#..", "###.", "...."],
+            (PieceType::TShape, West) => [".#..", ".##.", ".#..", "...."],
+            (PieceType::LShapeInverted, North) => ["....", "###.", "..#.", "...."],
+            (PieceType::LShapeInverted, East) => [".#..", ".#..", "##..", "...."],
+            (PieceType::LShapeInverted, South) => ["....", "#...", "###.", "...."],
+            (PieceType::LShapeInverted, West) => [".##.", ".#..", ".#..", "...."],
+            (PieceType::LShape, North) => ["....", "###.", "#...", "...."],
+            (PieceType::LShape, East) => ["##..", ".#..", ".#..", "...."],
+            (PieceType::LShape, South) => ["....", "..#.", "###.", "...."],
+            (PieceType::LShape, West) => [".#..", ".#..", ".##.", "...."],
+        };
+        shape(rows)
+    }
+
+    fn kicks(
+        &self,
+        p: &Piece,
+        pos: &Position,
+        to: Direction,
+        occupied: &dyn Fn(GridPosition) -> bool,
+    ) -> Vec<GridPosition> {
+        let in_place = GridPosition::new(0, 0);
+        let kicks = vec![in_place, GridPosition::new(1, 0), GridPosition::new(-1, 0)];
+        match p.piece_type {
+            PieceType::IShape | PieceType::OShape => vec![in_place],
+            PieceType::LShape | PieceType::LShapeInverted | PieceType::TShape => {
+                // center column rule: if the first cell in the way (in reading
+                // order) is in the middle column, the piece doesn't kick
+                let mut cells = self.blocks(p.piece_type, to);
+                cells.sort_by_key(|b| (b.y, b.x));
+                let center = pos.grid_position();
+                match cells.iter().find(|&&b| occupied(center + b)) {
+                    Some(b) if b.x == 0 => vec![in_place],
+                    _ => kicks,
+                }
+            }
+            _ => kicks,
+        }
+    }
+}
+
+// right handed rotation from the Nintendo games, without any kicks
+pub struct Classic;
+
+impl RotationSystem for Classic {
+    fn spawn_facing(&self, _piece_type: PieceType) -> Direction {
+        Direction::North
+    }
+
+    fn blocks(&self, piece_type: PieceType, facing: Direction) -> [GridPosition; 4] {
+        use Direction::*;
+        let north = match (piece_type, facing) {
+            (PieceType::IShape, North) | (PieceType::IShape, South) => {
+                return shape(["....", "####", "....", "...."])
+            }
+            (PieceType::IShape, _) => return shape([".#..", ".#..", ".#..", ".#.."]),
+            (PieceType::OShape, _) => return shape(["....", "##..", "##..", "...."]),
+            (PieceType::RShape, North) | (PieceType::RShape, South) => {
+                return shape(["....", "##..", ".##.", "...."])
+            }
+            (PieceType::RShape, _) => return shape(["..#.", ".##.", ".#..", "...."]),
+            (PieceType::RShapeInverted, North) | (PieceType::RShapeInverted, South) => {
+                return shape(["....", ".##.", "##..", "...."])
+            }
+            (PieceType::RShapeInverted, _) => return shape([".#..", ".##.", "..#.", "...."]),
+            (PieceType::TShape, _) => shape(["....", "###.", ".#..", "...."]),
+            (PieceType::LShapeInverted, _) => shape(["....", "###.", "..#.", "...."]),
+            (PieceType::LShape, _) => shape(["....", "###.", "#...", "...."]),
+        };
+        rotate_blocks(north, facing)
+    }
+
+    fn kicks(
+        &self,
+        _p: &Piece,
+        _pos: &Position,
+        _to: Direction,
+        _occupied: &dyn Fn(GridPosition) -> bool,
+    ) -> Vec<GridPosition> {
+        vec![GridPosition::new(0, 0)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIRECTIONS: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+
+    #[test]
+    fn every_state_has_four_distinct_blocks() {
+        for kind in RotationKind::ALL.iter() {
+            for &piece_type in PieceType::ALL.iter() {
+                for &facing in DIRECTIONS.iter() {
+                    let mut blocks = kind.system().blocks(piece_type, facing).to_vec();
+                    blocks.sort_by_key(|b| (b.x, b.y));
+                    blocks.dedup();
+                    assert_eq!(blocks.len(), 4, "{:?} {:?} {:?}", kind, piece_type, facing);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn srs_kicks_undo_each_other() {
+        for &piece_type in PieceType::ALL.iter() {
+            for &from in DIRECTIONS.iter() {
+                let to = from.cw();
+                let mut p = Piece::new(piece_type, RotationKind::Srs);
+                p.facing = from;
+                let there = Srs.kicks(&p, &p.pos, to, &|_| false);
+                p.facing = to;
+                let back = Srs.kicks(&p, &p.pos, from, &|_| false);
+                for (a, b) in there.iter().zip(back.iter()) {
+                    assert_eq!(*a, -*b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ars_center_column_blocks_kicks() {
+        let p = Piece::new(PieceType::TShape, RotationKind::Ars);
+        let center = p.pos.grid_position();
+        // something sitting right above the T stops it from kicking
+        let above = |cell: GridPosition| cell == center + GridPosition::new(0, -1);
+        assert_eq!(
+            Ars.kicks(&p, &p.pos, Direction::East, &above),
+            vec![GridPosition::new(0, 0)]
+        );
+        // but a wall to the left doesn't
+        let wall = |cell: GridPosition| cell.x < center.x;
+        assert_eq!(Ars.kicks(&p, &p.pos, Direction::East, &wall).len(), 3);
+    }
+
+    #[test]
+    fn classic_never_kicks() {
+        for &piece_type in PieceType::ALL.iter() {
+            let p = Piece::new(piece_type, RotationKind::Classic);
+            let kicks = Classic.kicks(&p, &p.pos, Direction::East, &|_| true);
+            assert_eq!(kicks, vec![GridPosition::new(0, 0)]);
+        }
+    }
+}
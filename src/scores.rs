@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::constants::{LEADERBOARD_SIZE, NAME_LENGTH};
+use crate::mode::GameMode;
+
+// one finished game on the high score table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    pub name: String,
+    pub score: u32,
+    pub lines: u32,
+    pub level: u32,
+    // seconds played, not counting the countdown
+    pub duration: f32,
+    // when the game ended, in seconds since the unix epoch
+    pub date: u64,
+    pub seed: u64,
+}
+
+// the best scores for every mode, kept in a file in the user data directory
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HighScores {
+    // best first, keyed by the mode's name since toml only allows string
+    // keys
+    #[serde(default)]
+    modes: BTreeMap<String, Vec<ScoreEntry>>,
+    // what was entered last time, to start the next name from
+    #[serde(default)]
+    pub last_name: String,
+}
+
+impl HighScores {
+    pub const FILE_NAME: &'static str = "scores.toml";
+
+    pub fn table(&self, mode: GameMode) -> &[ScoreEntry] {
+        self.modes
+            .get(&mode.name())
+            .map_or(&[], |entries| entries.as_slice())
+    }
+
+    // the place `entry` would take on the table counting from 0, or None if it
+    // wouldn't make the cut. races go by time and everything else by score,
+    // with ties going to whoever got there first.
+    pub fn place(&self, mode: GameMode, entry: &ScoreEntry) -> Option<usize> {
+        let place = self
+            .table(mode)
+            .iter()
+            .take_while(|e| {
+                if mode.is_race() {
+                    e.duration <= entry.duration
+                } else {
+                    e.score >= entry.score
+                }
+            })
+            .count();
+        if place < LEADERBOARD_SIZE {
+            Some(place)
+        } else {
+            None
+        }
+    }
+
+    pub fn insert(&mut self, mode: GameMode, entry: ScoreEntry) -> Option<usize> {
+        let place = self.place(mode, &entry)?;
+        let entries = self.modes.entry(mode.name()).or_default();
+        entries.insert(place, entry);
+        entries.truncate(LEADERBOARD_SIZE);
+        Some(place)
+    }
+}
+
+// what a name on the table can be made of, in the order the picker goes
+// through them
+pub const NAME_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 ";
+
+// choosing a name a letter at a time, arcade style, so it works with a
+// gamepad as well as a keyboard
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamePicker {
+    letters: Vec<char>,
+    cursor: usize,
+}
+
+impl NamePicker {
+    // starts from `name`, usually the last one entered
+    pub fn new(name: &str) -> Self {
+        let mut letters: Vec<char> = name
+            .to_uppercase()
+            .chars()
+            .filter(|&c| NAME_CHARS.contains(c))
+            .take(NAME_LENGTH)
+            .collect();
+        if letters.is_empty() {
+            letters.push('A');
+        }
+        letters.resize(NAME_LENGTH, ' ');
+        NamePicker { letters, cursor: 0 }
+    }
+
+    pub fn letters(&self) -> &[char] {
+        &self.letters
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    // turns the letter under the cursor forwards or back through NAME_CHARS
+    pub fn change_letter(&mut self, step: isize) {
+        let chars: Vec<char> = NAME_CHARS.chars().collect();
+        let current = &mut self.letters[self.cursor];
+        let i = chars.iter().position(|c| c == current).unwrap_or(0) as isize;
+        *current = chars[(i + step).rem_euclid(chars.len() as isize) as usize];
+    }
+
+    pub fn move_cursor(&mut self, step: isize) {
+        let cursor = self.cursor as isize + step;
+        self.cursor = cursor.max(0).min(NAME_LENGTH as isize - 1) as usize;
+    }
+
+    // puts a typed letter under the cursor and moves on, ignoring anything
+    // that can't be in a name
+    pub fn type_char(&mut self, c: char) {
+        let c = c.to_ascii_uppercase();
+        if NAME_CHARS.contains(c) {
+            self.letters[self.cursor] = c;
+            self.move_cursor(1);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if self.letters[self.cursor] == ' ' {
+            self.move_cursor(-1);
+        }
+        self.letters[self.cursor] = ' ';
+    }
+
+    pub fn name(&self) -> String {
+        let name: String = self.letters.iter().collect();
+        match name.trim() {
+            "" => "???".to_string(),
+            name => name.to_string(),
+        }
+    }
+}
+
+// a unix time as a year-month-day date, in UTC
+pub fn format_date(secs: u64) -> String {
+    // days to a civil date, from Howard Hinnant's date algorithms
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, score: u32) -> ScoreEntry {
+        timed(name, score, 60.0)
+    }
+
+    fn timed(name: &str, score: u32, duration: f32) -> ScoreEntry {
+        ScoreEntry {
+            name: name.to_string(),
+            score,
+            lines: score / 100,
+            level: 1,
+            duration,
+            date: 0,
+            seed: 1,
+        }
+    }
+
+    #[test]
+    fn keeps_the_top_scores_for_each_mode() {
+        let mut scores = HighScores::default();
+        let marathon = GameMode::Marathon(None);
+        for score in 1..=LEADERBOARD_SIZE as u32 {
+            scores.insert(marathon, entry("AAA", score * 100));
+        }
+        assert_eq!(
+            scores.table(marathon)[0].score,
+            LEADERBOARD_SIZE as u32 * 100
+        );
+        assert_eq!(scores.place(marathon, &entry("BBB", 50)), None);
+        assert_eq!(scores.insert(marathon, entry("BBB", 50)), None);
+        // a tie goes below the score that was already there
+        assert_eq!(scores.insert(marathon, entry("CCC", 500)), Some(6));
+        assert_eq!(scores.table(marathon)[5].name, "AAA");
+        assert_eq!(scores.table(marathon).len(), LEADERBOARD_SIZE);
+        assert_eq!(scores.table(marathon).last().unwrap().score, 200);
+
+        assert!(scores.table(GameMode::Marathon(Some(150))).is_empty());
+        assert_eq!(
+            scores.place(GameMode::Sprint(40), &entry("DDD", 0)),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn races_are_ranked_by_time() {
+        let mut scores = HighScores::default();
+        let sprint = GameMode::Sprint(40);
+        assert_eq!(scores.insert(sprint, timed("AAA", 9000, 70.0)), Some(0));
+        assert_eq!(scores.insert(sprint, timed("BBB", 100, 55.5)), Some(0));
+        // a tie goes below the time that was already there
+        assert_eq!(scores.insert(sprint, timed("CCC", 500, 70.0)), Some(2));
+        let names: Vec<&str> = scores
+            .table(sprint)
+            .iter()
+            .map(|e| e.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["BBB", "AAA", "CCC"]);
+        for _ in 0..LEADERBOARD_SIZE {
+            scores.insert(sprint, timed("DDD", 0, 60.0));
+        }
+        assert_eq!(scores.place(sprint, &timed("EEE", 99_999, 61.0)), None);
+        assert_eq!(scores.place(sprint, &timed("EEE", 0, 50.0)), Some(0));
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut scores = HighScores::default();
+        scores.insert(GameMode::Sprint(40), entry("ABC", 1200));
+        scores.insert(GameMode::Marathon(None), entry("XYZ", 99_000));
+        scores.last_name = "XYZ".to_string();
+        let text = toml::to_string(&toml::Value::try_from(&scores).unwrap()).unwrap();
+        let loaded: HighScores = toml::from_str(&text).unwrap();
+        assert_eq!(loaded, scores);
+        assert_eq!(
+            toml::from_str::<HighScores>("").unwrap(),
+            HighScores::default()
+        );
+    }
+
+    #[test]
+    fn names_are_picked_a_letter_at_a_time() {
+        let mut picker = NamePicker::new("");
+        assert_eq!(picker.name(), "A");
+        picker.change_letter(-1);
+        assert_eq!(picker.letters()[0], ' ');
+        assert_eq!(picker.name(), "???");
+        picker.change_letter(1);
+        picker.change_letter(2);
+        picker.move_cursor(1);
+        picker.type_char('x');
+        picker.type_char('!');
+        assert_eq!(picker.cursor(), 2);
+        picker.type_char('9');
+        assert_eq!(picker.cursor(), 2);
+        assert_eq!(picker.name(), "CX9");
+        picker.backspace();
+        picker.backspace();
+        assert_eq!(picker.cursor(), 1);
+        assert_eq!(picker.name(), "C");
+
+        assert_eq!(NamePicker::new("dave").name(), "DAV");
+    }
+
+    #[test]
+    fn dates_are_in_utc() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_792_108_800 + 86_399), "2026-10-16");
+    }
+}
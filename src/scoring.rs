@@ -0,0 +1,309 @@
+use crate::piece::{Piece, PieceType};
+use crate::types::GridPosition;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Spin {
+    None,
+    Mini,
+    Full,
+}
+
+// the 3-corner rule. `kick` is the index of the kick test used by the last
+// rotation, or None if the piece has moved or fallen since it was rotated.
+pub fn t_spin(p: &Piece, kick: Option<usize>, occupied: &dyn Fn(GridPosition) -> bool) -> Spin {
+    let kick = match kick {
+        Some(kick) if p.piece_type == PieceType::TShape => kick,
+        _ => return Spin::None,
+    };
+    let cells: Vec<GridPosition> = p
+        .get_blocks(&p.pos)
+        .iter()
+        .map(|b| b.pos.grid_position())
+        .collect();
+    let is_neighbour =
+        |a: &GridPosition, b: &GridPosition| (a.x - b.x).abs() + (a.y - b.y).abs() == 1;
+
+    // the center touches every other block, and the nub is the one without a
+    // block across from it
+    let center = match cells
+        .iter()
+        .find(|c| cells.iter().filter(|o| is_neighbour(c, o)).count() == 3)
+    {
+        Some(center) => *center,
+        None => return Spin::None,
+    };
+    let nub = match cells
+        .iter()
+        .find(|&&c| c != center && !cells.contains(&(center * 2 - c)))
+    {
+        Some(nub) => *nub,
+        None => return Spin::None,
+    };
+    let front = nub - center;
+    let side = GridPosition::new(-front.y, front.x);
+
+    let front_corners = [center + front + side, center + front - side];
+    let back_corners = [center - front + side, center - front - side];
+    let front_count = front_corners.iter().filter(|&&c| occupied(c)).count();
+    let back_count = back_corners.iter().filter(|&&c| occupied(c)).count();
+
+    if front_count + back_count < 3 {
+        Spin::None
+    } else if front_count == 2 || p.rotation.system().upgrades_t_spin(kick) {
+        Spin::Full
+    } else {
+        Spin::Mini
+    }
+}
+
+// guideline points for a lock, before multiplying by the level
+pub fn clear_points(lines: u32, spin: Spin) -> u32 {
+    match (spin, lines) {
+        (Spin::Full, 0) => 400,
+        (Spin::Full, 1) => 800,
+        (Spin::Full, 2) => 1200,
+        (Spin::Full, 3) => 1600,
+        (Spin::Mini, 0) => 100,
+        (Spin::Mini, 1) => 200,
+        (Spin::Mini, 2) => 400,
+        (_, 0) => 0,
+        (_, 1) => 100,
+        (_, 2) => 300,
+        (_, 3) => 500,
+        (_, 4) => 800,
+        _ => panic!("Cleared more than 4 lines?"),
+    }
+}
+
+// bonus for emptying the board, before multiplying by the level
+pub fn perfect_clear_points(lines: u32, back_to_back: bool) -> u32 {
+    match lines {
+        1 => 800,
+        2 => 1200,
+        3 => 1800,
+        4 if back_to_back => 3200,
+        4 => 2000,
+        _ => 0,
+    }
+}
+
+// what the HUD calls a lock worth announcing, e.g. "T-SPIN DOUBLE"
+pub fn clear_name(lines: u32, spin: Spin) -> Option<String> {
+    let count = match lines {
+        0 => "",
+        1 => " SINGLE",
+        2 => " DOUBLE",
+        3 => " TRIPLE",
+        _ => "",
+    };
+    match spin {
+        Spin::Full => Some(format!("T-SPIN{}", count)),
+        Spin::Mini => Some(format!("MINI T-SPIN{}", count)),
+        Spin::None if lines == 4 => Some("TETRIS".to_string()),
+        Spin::None => None,
+    }
+}
+
+// everything a single lock was worth
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clear {
+    pub lines: u32,
+    pub spin: Spin,
+    // how many clears in a row came right before this one
+    pub combo: u32,
+    pub back_to_back: bool,
+    pub perfect: bool,
+    pub points: u32,
+}
+
+impl Clear {
+    // one line per part of the announcement, or None if there's nothing to say
+    pub fn name(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(name) = clear_name(self.lines, self.spin) {
+            if self.back_to_back {
+                parts.push("B2B".to_string());
+            }
+            parts.push(name);
+        }
+        if self.combo > 0 {
+            parts.push(format!("COMBO {}", self.combo));
+        }
+        if self.perfect {
+            parts.push("PERFECT CLEAR".to_string());
+        }
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("\n"))
+        }
+    }
+}
+
+// what scoring remembers from one lock to the next
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Chain {
+    // clears in a row minus one, or None if the last lock didn't clear
+    pub combo: Option<u32>,
+    pub max_combo: u32,
+    // tetrises and t-spin clears in a row, with no easier clear in between
+    pub back_to_back: u32,
+    pub perfect_clears: u32,
+}
+
+impl Chain {
+    pub fn score(&mut self, lines: u32, spin: Spin, perfect: bool, level: u32) -> Clear {
+        let mut points = clear_points(lines, spin);
+        let mut combo = 0;
+        let mut back_to_back = false;
+        // locks that don't clear anything break the combo but not the
+        // back-to-back chain
+        if lines > 0 {
+            let difficult = lines == 4 || spin != Spin::None;
+            if difficult {
+                back_to_back = self.back_to_back > 0;
+                self.back_to_back += 1;
+            } else {
+                self.back_to_back = 0;
+            }
+            if back_to_back {
+                points = points * 3 / 2;
+            }
+            combo = self.combo.map_or(0, |c| c + 1);
+            self.combo = Some(combo);
+            self.max_combo = self.max_combo.max(combo);
+            points += 50 * combo;
+        } else {
+            self.combo = None;
+        }
+        let perfect = perfect && lines > 0;
+        if perfect {
+            self.perfect_clears += 1;
+            points += perfect_clear_points(lines, back_to_back);
+        }
+        Clear {
+            lines,
+            spin,
+            combo,
+            back_to_back,
+            perfect,
+            points: points * level,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::Direction;
+    use crate::position::Position;
+    use crate::rotation::RotationKind;
+
+    fn t_at(x: f32, y: f32, facing: Direction) -> Piece {
+        let mut p = Piece::new(PieceType::TShape, RotationKind::Srs);
+        p.pos = Position::new(x, y);
+        p.facing = facing;
+        p
+    }
+
+    fn filled(cells: &'static [(i32, i32)]) -> impl Fn(GridPosition) -> bool {
+        move |c| cells.iter().any(|&(x, y)| c == GridPosition::new(x, y))
+    }
+
+    #[test]
+    fn three_corners_with_both_front_corners_is_full() {
+        // T pointing down into a slot, with the corners left, right and under
+        let p = t_at(4., 10., Direction::South);
+        let occupied = filled(&[(3, 11), (5, 11), (3, 9)]);
+        assert_eq!(t_spin(&p, Some(0), &occupied), Spin::Full);
+    }
+
+    #[test]
+    fn three_corners_with_one_front_corner_is_mini() {
+        let p = t_at(4., 10., Direction::South);
+        let occupied = filled(&[(3, 11), (3, 9), (5, 9)]);
+        assert_eq!(t_spin(&p, Some(0), &occupied), Spin::Mini);
+        assert_eq!(t_spin(&p, Some(4), &occupied), Spin::Full);
+    }
+
+    #[test]
+    fn only_srs_kicks_upgrade_a_mini() {
+        let occupied = filled(&[(3, 11), (3, 9), (5, 9)]);
+        for &rotation in [RotationKind::Ars, RotationKind::Classic].iter() {
+            // pointing down, like the SRS one above, is north in these
+            let mut p = Piece::new(PieceType::TShape, rotation);
+            p.pos = Position::new(4., 10.);
+            for kick in 0..5 {
+                assert_eq!(t_spin(&p, Some(kick), &occupied), Spin::Mini);
+            }
+        }
+    }
+
+    #[test]
+    fn needs_a_rotation_and_three_corners() {
+        let p = t_at(4., 10., Direction::South);
+        let three = filled(&[(3, 11), (5, 11), (3, 9)]);
+        let two = filled(&[(3, 11), (5, 11)]);
+        assert_eq!(t_spin(&p, None, &three), Spin::None);
+        assert_eq!(t_spin(&p, Some(0), &two), Spin::None);
+
+        let mut o = t_at(4., 10., Direction::South);
+        o.piece_type = PieceType::OShape;
+        assert_eq!(t_spin(&o, Some(0), &three), Spin::None);
+    }
+
+    #[test]
+    fn guideline_points_and_names() {
+        assert_eq!(clear_points(2, Spin::Full), 1200);
+        assert_eq!(clear_points(0, Spin::Mini), 100);
+        assert_eq!(clear_points(4, Spin::None), 800);
+        assert_eq!(clear_name(2, Spin::Full).unwrap(), "T-SPIN DOUBLE");
+        assert_eq!(clear_name(1, Spin::Mini).unwrap(), "MINI T-SPIN SINGLE");
+        assert_eq!(clear_name(1, Spin::None), None);
+    }
+
+    #[test]
+    fn combos_grow_until_a_lock_clears_nothing() {
+        let mut chain = Chain::default();
+        assert_eq!(chain.score(1, Spin::None, false, 1).points, 100);
+        assert_eq!(chain.score(1, Spin::None, false, 1).points, 150);
+        let third = chain.score(2, Spin::None, false, 2);
+        assert_eq!(third.combo, 2);
+        assert_eq!(third.points, (300 + 100) * 2);
+        assert_eq!(third.name().unwrap(), "COMBO 2");
+        chain.score(0, Spin::None, false, 1);
+        assert_eq!(chain.combo, None);
+        assert_eq!(chain.max_combo, 2);
+        assert_eq!(chain.score(1, Spin::None, false, 1).combo, 0);
+    }
+
+    #[test]
+    fn back_to_back_needs_two_difficult_clears_in_a_row() {
+        let mut chain = Chain::default();
+        assert!(!chain.score(4, Spin::None, false, 1).back_to_back);
+        // a spin that clears nothing doesn't break the chain
+        chain.score(0, Spin::Full, false, 1);
+        let second = chain.score(2, Spin::Full, false, 1);
+        assert!(second.back_to_back);
+        assert_eq!(second.points, 1800);
+        assert_eq!(chain.back_to_back, 2);
+
+        chain.score(1, Spin::None, false, 1);
+        assert_eq!(chain.back_to_back, 0);
+        chain.score(0, Spin::None, false, 1);
+        assert!(!chain.score(4, Spin::None, false, 1).back_to_back);
+    }
+
+    #[test]
+    fn perfect_clear_adds_a_bonus() {
+        let mut chain = Chain::default();
+        let clear = chain.score(4, Spin::None, true, 1);
+        assert!(clear.perfect);
+        assert_eq!(clear.points, 800 + 2000);
+        assert_eq!(clear.name().unwrap(), "TETRIS\nPERFECT CLEAR");
+        chain.score(0, Spin::None, false, 1);
+        let b2b = chain.score(4, Spin::None, true, 1);
+        assert_eq!(b2b.points, 1200 + 3200);
+        assert_eq!(chain.perfect_clears, 2);
+    }
+}
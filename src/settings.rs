@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::Bindings;
+use crate::constants::{MAX_PREVIEW_COUNT, MAX_START_LEVEL, VISIBLE_ROWS};
+use crate::engine::GameOptions;
+use crate::lock::LockReset;
+use crate::mode::GameMode;
+
+// the smallest window the board and HUD still fit in
+const MIN_WINDOW_WIDTH: f32 = 320.0;
+const MIN_WINDOW_HEIGHT: f32 = 240.0;
+
+// the top of the field garbage has to leave clear for pieces to come in
+const SPAWN_ROOM: u32 = 2;
+const MAX_DIG_ROWS: u32 = VISIBLE_ROWS as u32 - SPAWN_ROOM;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Display {
+    pub width: f32,
+    pub height: f32,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub show_fps: bool,
+    // how solid the ghost piece is drawn, from 0 for invisible to 1
+    pub ghost_opacity: f32,
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Display {
+            width: 640.0,
+            height: 480.0,
+            fullscreen: false,
+            vsync: true,
+            show_fps: true,
+            ghost_opacity: 0.05,
+        }
+    }
+}
+
+// everything the player can change, kept in a file in the user data directory.
+// anything left out of the file keeps its default.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub display: Display,
+    // what the menu starts out with, handling included
+    pub game: GameOptions,
+    pub controls: Bindings,
+}
+
+impl Settings {
+    pub const FILE_NAME: &'static str = "settings.toml";
+
+    pub fn parse(text: &str) -> Result<Settings, String> {
+        let settings: Settings = toml::from_str(text).map_err(|e| e.to_string())?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    // every value that's out of range, rather than just the first
+    pub fn validate(&self) -> Result<(), String> {
+        let display = &self.display;
+        let game = &self.game;
+        let handling = &game.handling;
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, problem: String| {
+            if !ok {
+                problems.push(problem);
+            }
+        };
+
+        check(
+            display.width >= MIN_WINDOW_WIDTH && display.height >= MIN_WINDOW_HEIGHT,
+            format!(
+                "display.width and display.height must be at least {}x{}, not {}x{}",
+                MIN_WINDOW_WIDTH, MIN_WINDOW_HEIGHT, display.width, display.height
+            ),
+        );
+        check(
+            (0.0..=1.0).contains(&display.ghost_opacity),
+            format!(
+                "display.ghost_opacity must be between 0 and 1, not {}",
+                display.ghost_opacity
+            ),
+        );
+        check(
+            handling.das >= 0.0,
+            format!(
+                "game.handling.das can't be negative, but is {}",
+                handling.das
+            ),
+        );
+        check(
+            handling.arr >= 0.0,
+            format!(
+                "game.handling.arr can't be negative, but is {}",
+                handling.arr
+            ),
+        );
+        check(
+            handling.soft_drop_factor >= 1.0,
+            format!(
+                "game.handling.soft_drop_factor must be at least 1, not {}",
+                handling.soft_drop_factor
+            ),
+        );
+        check(
+            game.lock_delay > 0.0,
+            format!("game.lock_delay must be above 0, not {}", game.lock_delay),
+        );
+        check(
+            game.line_clear_delay >= 0.0,
+            format!(
+                "game.line_clear_delay can't be negative, but is {}",
+                game.line_clear_delay
+            ),
+        );
+        check(
+            (1..=MAX_PREVIEW_COUNT).contains(&game.preview_count),
+            format!(
+                "game.preview_count must be between 1 and {}, not {}",
+                MAX_PREVIEW_COUNT, game.preview_count
+            ),
+        );
+        check(
+            (1..=MAX_START_LEVEL).contains(&game.start_level),
+            format!(
+                "game.start_level must be between 1 and {}, not {}",
+                MAX_START_LEVEL, game.start_level
+            ),
+        );
+        if let GameMode::Dig(rows) = game.mode {
+            check(
+                (1..=MAX_DIG_ROWS).contains(&rows),
+                format!(
+                    "game.mode Dig rows must be between 1 and {}, not {}",
+                    MAX_DIG_ROWS, rows
+                ),
+            );
+        }
+        check(
+            game.mode.line_goal() != Some(0),
+            "game.mode line goal must be above 0".to_string(),
+        );
+        check(
+            game.mode.time_limit() != Some(0.0),
+            "game.mode Ultra time limit must be above 0".to_string(),
+        );
+        check(
+            game.lock_reset != LockReset::Move(0),
+            "game.lock_reset Move limit must be above 0".to_string(),
+        );
+        check(
+            (0.0..=1.0).contains(&game.messiness),
+            format!(
+                "game.messiness must be between 0 and 1, not {}",
+                game.messiness
+            ),
+        );
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("; "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::randomizer::RandomizerKind;
+
+    #[test]
+    fn missing_values_keep_their_defaults() {
+        assert_eq!(Settings::parse("").unwrap(), Settings::default());
+        let settings = Settings::parse(
+            "[display]\nvsync = false\n\n[game]\nrandomizer = \"Classic\"\n\n\
+             [game.handling]\ndas = 0.1\n",
+        )
+        .unwrap();
+        assert!(!settings.display.vsync);
+        assert_eq!(settings.display.width, 640.0);
+        assert_eq!(settings.game.randomizer, RandomizerKind::Classic);
+        assert_eq!(settings.game.handling.das, 0.1);
+        assert_eq!(
+            settings.game.handling.arr,
+            GameOptions::default().handling.arr
+        );
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut settings = Settings::default();
+        settings.display.fullscreen = true;
+        settings.game.preview_count = 5;
+        settings.controls.hold.keyboard = vec!["H".to_string()];
+        let text = toml::to_string(&toml::Value::try_from(&settings).unwrap()).unwrap();
+        assert_eq!(Settings::parse(&text).unwrap(), settings);
+    }
+
+    #[test]
+    fn reports_every_bad_value() {
+        let err = Settings::parse(
+            "[display]\nghost_opacity = 2.0\n\n[game]\npreview_count = 0\n\n\
+             [game.handling]\narr = -1.0\n",
+        )
+        .unwrap_err();
+        assert!(err.contains("display.ghost_opacity"));
+        assert!(err.contains("game.preview_count"));
+        assert!(err.contains("game.handling.arr"));
+        assert!(!err.contains("game.handling.das"));
+
+        let err = Settings::parse(
+            "[game.mode]\nkind = \"Dig\"\nvalue = 24\n\n\
+             [game.lock_reset]\nkind = \"Move\"\nvalue = 0\n",
+        )
+        .unwrap_err();
+        assert!(err.contains("Dig rows"));
+        assert!(err.contains("game.lock_reset"));
+        for mode in [
+            "kind = \"Sprint\"\nvalue = 0",
+            "kind = \"Ultra\"\nvalue = 0",
+        ]
+        .iter()
+        {
+            let text = format!("[game.mode]\n{}\n", mode);
+            assert!(Settings::parse(&text).unwrap_err().contains("game.mode"));
+        }
+        let text = "[game.mode]\nkind = \"Marathon\"\nvalue = 0\n";
+        assert!(Settings::parse(text).unwrap_err().contains("line goal"));
+        let text = "[game.mode]\nkind = \"Dig\"\nvalue = 18\n";
+        assert!(Settings::parse(text).is_ok());
+
+        let err = Settings::parse("[display]\nwidht = 800.0\n").unwrap_err();
+        assert!(err.contains("widht"));
+        let err = Settings::parse("[game]\nlock_dealy = 1.0\n").unwrap_err();
+        assert!(err.contains("lock_dealy"));
+        let err = Settings::parse("[game.handling]\ndsa = 0.1\n").unwrap_err();
+        assert!(err.contains("dsa"));
+        assert!(Settings::parse("[display]\nwidth = \"big\"\n").is_err());
+    }
+}
@@ -1,82 +1,35 @@
 use std::cell::RefCell;
-use std::collections::VecDeque;
-use std::fmt;
+use std::fs;
 use std::rc::Rc;
-use std::time::{Duration, Instant};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use ggez::audio::SoundSource;
 use ggez::event::{Axis, Button, EventHandler, GamepadId, KeyCode, KeyMods};
+use ggez::filesystem;
 use ggez::graphics;
 use ggez::timer;
-use ggez::{Context, GameResult};
+use ggez::{Context, GameError, GameResult};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 
 use crate::assets::Assets;
+use crate::bindings::{Action, Bindings, Device};
 use crate::constants::{
-    ASPECT_RATIO, BASE_SPEED, COLUMNS, INPUT_DELAY, MOVEMENT_DELAY, ROWS, SPEED_PER_LEVEL,
+    ANNOUNCE_TIME, ASPECT_RATIO, BUFFER_ROWS, COLUMNS, MAX_PREVIEW_COUNT, MAX_START_LEVEL, ROWS,
 };
-use crate::piece::{create_random_piece, Block, Piece};
+use crate::engine::{Game, GameOptions, InputState, TopOut};
+use crate::garbage::MESSINESS_LEVELS;
+use crate::gravity::GravityKind;
+use crate::lock::LockReset;
+use crate::mode::GameMode;
+use crate::piece::{Block, Piece};
 use crate::position::Position;
+use crate::randomizer::RandomizerKind;
+use crate::replay::{Playback, Recorder, Replay, REPLAY_SPEEDS, SEEK_SECONDS};
+use crate::rotation::RotationKind;
+use crate::scores::{format_date, HighScores, NamePicker, ScoreEntry};
+use crate::settings::Settings;
 use crate::types::{Point2, Vec2};
 
-struct GridState([[Option<Block>; COLUMNS]; ROWS]);
-
-impl fmt::Display for GridState {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for i in 0..ROWS {
-            for j in 0..COLUMNS {
-                match self.0[i][j] {
-                    Some(_) => write!(f, "x")?,
-                    None => write!(f, "o")?,
-                };
-            }
-            write!(f, "\n")?;
-        }
-        Ok(())
-    }
-}
-
-struct InputState {
-    left: bool,
-    right: bool,
-    down: bool,
-    up: bool,
-    rotate_right: bool,
-    rotate_left: bool,
-    hold: bool,
-}
-
-impl Default for InputState {
-    fn default() -> Self {
-        InputState {
-            left: false,
-            right: false,
-            down: false,
-            up: false,
-            rotate_right: false,
-            rotate_left: false,
-            hold: false,
-        }
-    }
-}
-
-impl InputState {
-    fn moved(&self) -> bool {
-        if vec![self.left, self.right, self.down].iter().any(|&x| x) {
-            return true;
-        }
-        false
-    }
-    fn acted(&self) -> bool {
-        if vec![self.up, self.rotate_left, self.rotate_right]
-            .iter()
-            .any(|&x| x)
-        {
-            return true;
-        }
-        false
-    }
-}
-
 gfx_defines! {
     constant Opacity {
         pct: f32 = "u_Pct",
@@ -105,14 +58,27 @@ struct Transition {
 
 struct GameOverScene {
     state: SharedState,
+    options: GameOptions,
+    reason: TopOut,
+    replay: Option<Replay>,
     restart: bool,
+    watch: bool,
 }
 
 impl GameOverScene {
-    fn new(state: &SharedState) -> Self {
+    fn new(
+        state: &SharedState,
+        options: &GameOptions,
+        reason: TopOut,
+        replay: Option<Replay>,
+    ) -> Self {
         Self {
             state: state.clone(),
+            options: options.clone(),
+            reason,
+            replay,
             restart: false,
+            watch: false,
         }
     }
 }
@@ -146,11 +112,48 @@ impl EventHandler for GameOverScene {
             .offset(Point2::new(0.5, 0.5));
         graphics::draw(ctx, &title_display, title_params)?;
 
+        let reason_str = self.reason.name().to_uppercase();
+        let reason_display = graphics::Text::new((reason_str, state.assets.font, 14.0));
+        let reason_dest = Point2::new(
+            screen_w / 2.0 - reason_display.width(ctx) as f32 / 2.0,
+            screen_h / 2.0 + 40.0,
+        );
+        let reason_params = graphics::DrawParam::default()
+            .dest(reason_dest)
+            .offset(Point2::new(0.5, 0.5));
+        graphics::draw(ctx, &reason_display, reason_params)?;
+
+        let instructions = "R: watch replay   Any key: retry";
+        let inst_display = graphics::Text::new((instructions, state.assets.font, 14.0));
+        let inst_dest = Point2::new(
+            screen_w / 2.0 - inst_display.width(ctx) as f32 / 2.0,
+            screen_h / 2.0 + 80.0,
+        );
+        let inst_params = graphics::DrawParam::default()
+            .dest(inst_dest)
+            .offset(Point2::new(0.5, 0.5));
+        graphics::draw(ctx, &inst_display, inst_params)?;
+
         Ok(())
     }
 
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        repeat: bool,
+    ) {
+        match keycode {
+            _ if repeat => (),
+            KeyCode::R => self.watch = true,
+            _ => self.restart = true,
+        }
+    }
+
     fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
         match btn {
+            Button::North => self.watch = true,
             _ => self.restart = true,
         }
     }
@@ -165,7 +168,634 @@ impl Scene for GameOverScene {
         if self.restart {
             return Some(Transition {
                 transition_type: TransitionType::Reset,
-                scene: Some(Box::new(GameScene::new(&self.shared_state()))),
+                scene: Some(Box::new(GameScene::new(
+                    &self.shared_state(),
+                    &self.options,
+                ))),
+            });
+        } else if self.watch {
+            self.watch = false;
+            return watch_replay(&self.shared_state(), self.replay.as_ref());
+        }
+        None
+    }
+}
+
+// the end of a game that was won rather than topped out, like a finished sprint
+struct ResultsScene {
+    state: SharedState,
+    options: GameOptions,
+    title: String,
+    lines: Vec<String>,
+    replay: Option<Replay>,
+    restart: bool,
+    quit: bool,
+    watch: bool,
+}
+
+impl ResultsScene {
+    fn new(
+        state: &SharedState,
+        options: &GameOptions,
+        title: String,
+        lines: Vec<String>,
+        replay: Option<Replay>,
+    ) -> Self {
+        Self {
+            state: state.clone(),
+            options: options.clone(),
+            title,
+            lines,
+            replay,
+            restart: false,
+            quit: false,
+            watch: false,
+        }
+    }
+}
+
+impl EventHandler for ResultsScene {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let state = self.state.borrow();
+        let (screen_w, screen_h) = graphics::size(ctx);
+
+        let overlay = graphics::Rect::new(0.0, 0.0, screen_w, screen_h);
+        let overlay_rect = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            overlay,
+            graphics::Color::new(0.0, 0.0, 0.0, 0.8),
+        )?;
+        graphics::draw(ctx, &overlay_rect, graphics::DrawParam::default())?;
+
+        let title_display = graphics::Text::new((self.title.as_str(), state.assets.font, 24.0));
+        let title_dest = Point2::new(
+            screen_w / 2.0 - title_display.width(ctx) as f32 / 2.0,
+            screen_h / 3.0,
+        );
+        graphics::draw(ctx, &title_display, (title_dest, 0.0, graphics::WHITE))?;
+
+        for (i, line) in self.lines.iter().enumerate() {
+            let line_display = graphics::Text::new((line.as_str(), state.assets.font, 18.0));
+            let line_dest = Point2::new(
+                screen_w / 2.0 - line_display.width(ctx) as f32 / 2.0,
+                screen_h / 3.0 + 50.0 + 28.0 * i as f32,
+            );
+            graphics::draw(ctx, &line_display, (line_dest, 0.0, graphics::WHITE))?;
+        }
+
+        let instructions = "R: replay   Any key: retry   Esc: menu";
+        let inst_display = graphics::Text::new((instructions, state.assets.font, 14.0));
+        let inst_dest = Point2::new(
+            screen_w / 2.0 - inst_display.width(ctx) as f32 / 2.0,
+            screen_h / 3.0 + 80.0 + 28.0 * self.lines.len() as f32,
+        );
+        graphics::draw(ctx, &inst_display, (inst_dest, 0.0, graphics::WHITE))?;
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        repeat: bool,
+    ) {
+        match keycode {
+            _ if repeat => (),
+            KeyCode::Escape => self.quit = true,
+            KeyCode::R => self.watch = true,
+            _ => self.restart = true,
+        }
+    }
+
+    fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
+        match btn {
+            Button::Select => self.quit = true,
+            Button::North => self.watch = true,
+            _ => self.restart = true,
+        }
+    }
+}
+
+impl Scene for ResultsScene {
+    fn shared_state(&self) -> SharedState {
+        self.state.clone()
+    }
+
+    fn get_transition(&mut self) -> Option<Transition> {
+        if self.restart {
+            return Some(Transition {
+                transition_type: TransitionType::Reset,
+                scene: Some(Box::new(GameScene::new(
+                    &self.shared_state(),
+                    &self.options,
+                ))),
+            });
+        } else if self.quit {
+            return Some(Transition {
+                transition_type: TransitionType::Reset,
+                scene: Some(Box::new(IntroScene::with_options(
+                    &self.shared_state(),
+                    &self.options,
+                ))),
+            });
+        } else if self.watch {
+            self.watch = false;
+            return watch_replay(&self.shared_state(), self.replay.as_ref());
+        }
+        None
+    }
+}
+
+// asks for a name to go with a game that made the high score table, then
+// moves on to whatever comes after the game
+struct NameEntryScene {
+    state: SharedState,
+    mode: GameMode,
+    entry: ScoreEntry,
+    picker: NamePicker,
+    next: Option<Box<dyn Scene>>,
+    done: bool,
+}
+
+impl NameEntryScene {
+    fn new(
+        state: &SharedState,
+        mode: GameMode,
+        entry: ScoreEntry,
+        last_name: &str,
+        next: Box<dyn Scene>,
+    ) -> Self {
+        Self {
+            state: state.clone(),
+            mode,
+            entry,
+            picker: NamePicker::new(last_name),
+            next: Some(next),
+            done: false,
+        }
+    }
+
+    // the table is loaded again rather than kept from the end of the game, in
+    // case another window saved to it in the meantime
+    fn save(&mut self, ctx: &Context) {
+        let mut scores: HighScores =
+            load_user_file(ctx, HighScores::FILE_NAME).unwrap_or_else(|e| {
+                println!("Couldn't load high scores: {}", e);
+                HighScores::default()
+            });
+        let mut entry = self.entry.clone();
+        entry.name = self.picker.name();
+        scores.last_name = entry.name.clone();
+        scores.insert(self.mode, entry);
+        if let Err(e) = save_user_file(ctx, HighScores::FILE_NAME, &scores) {
+            println!("Couldn't save high scores: {}", e);
+        }
+        self.done = true;
+    }
+}
+
+impl EventHandler for NameEntryScene {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let state = self.state.borrow();
+        let (screen_w, screen_h) = graphics::size(ctx);
+
+        let overlay = graphics::Rect::new(0.0, 0.0, screen_w, screen_h);
+        let overlay_rect = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            overlay,
+            graphics::Color::new(0.0, 0.0, 0.0, 0.8),
+        )?;
+        graphics::draw(ctx, &overlay_rect, graphics::DrawParam::default())?;
+
+        let title_str = format!("NEW HIGH SCORE: {}", self.entry.score);
+        let title_display = graphics::Text::new((title_str, state.assets.font, 24.0));
+        let title_dest = Point2::new(
+            screen_w / 2.0 - title_display.width(ctx) as f32 / 2.0,
+            screen_h / 3.0,
+        );
+        graphics::draw(ctx, &title_display, (title_dest, 0.0, graphics::WHITE))?;
+
+        // one box per letter, with the one being changed picked out
+        let letter_w = 40.0;
+        let left = screen_w / 2.0 - letter_w * self.picker.letters().len() as f32 / 2.0;
+        for (i, &letter) in self.picker.letters().iter().enumerate() {
+            let color = if i == self.picker.cursor() {
+                graphics::Color::new(1.0, 0.85, 0.2, 1.0)
+            } else {
+                graphics::WHITE
+            };
+            let shown = if letter == ' ' { '_' } else { letter };
+            let letter_display = graphics::Text::new((shown.to_string(), state.assets.font, 32.0));
+            let letter_dest = Point2::new(
+                left + letter_w * i as f32 + letter_w / 2.0
+                    - letter_display.width(ctx) as f32 / 2.0,
+                screen_h / 3.0 + 60.0,
+            );
+            graphics::draw(ctx, &letter_display, (letter_dest, 0.0, color))?;
+        }
+
+        let instructions = "Type or Up/Down: letter   Left/Right: move   Enter: done";
+        let inst_display = graphics::Text::new((instructions, state.assets.font, 14.0));
+        let inst_dest = Point2::new(
+            screen_w / 2.0 - inst_display.width(ctx) as f32 / 2.0,
+            screen_h / 3.0 + 120.0,
+        );
+        graphics::draw(ctx, &inst_display, (inst_dest, 0.0, graphics::WHITE))?;
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        _repeat: bool,
+    ) {
+        // letters come in through text_input_event
+        match keycode {
+            KeyCode::Up => self.picker.change_letter(1),
+            KeyCode::Down => self.picker.change_letter(-1),
+            KeyCode::Left => self.picker.move_cursor(-1),
+            KeyCode::Right => self.picker.move_cursor(1),
+            KeyCode::Back => self.picker.backspace(),
+            KeyCode::Return | KeyCode::NumpadEnter => self.save(ctx),
+            _ => (),
+        }
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        self.picker.type_char(character);
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, _id: GamepadId) {
+        match btn {
+            Button::South | Button::Start => self.save(ctx),
+            Button::East => self.picker.backspace(),
+            _ => (),
+        }
+    }
+
+    fn gamepad_axis_event(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        match axis {
+            Axis::DPadX if value < 0.0 => self.picker.move_cursor(-1),
+            Axis::DPadX if value > 0.0 => self.picker.move_cursor(1),
+            Axis::DPadY if value < 0.0 => self.picker.change_letter(-1),
+            Axis::DPadY if value > 0.0 => self.picker.change_letter(1),
+            _ => (),
+        }
+    }
+}
+
+impl Scene for NameEntryScene {
+    fn shared_state(&self) -> SharedState {
+        self.state.clone()
+    }
+
+    fn get_transition(&mut self) -> Option<Transition> {
+        if self.done {
+            return Some(Transition {
+                transition_type: TransitionType::Swap,
+                scene: self.next.take(),
+            });
+        }
+        None
+    }
+}
+
+// the high score table for one mode at a time
+struct HighScoresScene {
+    state: SharedState,
+    scores: HighScores,
+    mode: GameMode,
+    back: bool,
+}
+
+impl HighScoresScene {
+    fn new(state: &SharedState, scores: HighScores, mode: GameMode) -> Self {
+        Self {
+            state: state.clone(),
+            scores,
+            mode,
+            back: false,
+        }
+    }
+}
+
+impl EventHandler for HighScoresScene {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let state = self.state.borrow();
+        let (screen_w, screen_h) = graphics::size(ctx);
+
+        // covers up the intro underneath
+        let background = graphics::Rect::new(0.0, 0.0, screen_w, screen_h);
+        let background_rect = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            background,
+            graphics::BLACK,
+        )?;
+        graphics::draw(ctx, &background_rect, graphics::DrawParam::default())?;
+
+        let title_str = format!("< {} HIGH SCORES >", self.mode.name().to_uppercase());
+        let title_display = graphics::Text::new((title_str, state.assets.font, 24.0));
+        let title_dest = Point2::new(
+            screen_w / 2.0 - title_display.width(ctx) as f32 / 2.0,
+            screen_h / 8.0,
+        );
+        graphics::draw(ctx, &title_display, (title_dest, 0.0, graphics::WHITE))?;
+
+        let table = self.scores.table(self.mode);
+        let mut rows = vec![format!(
+            "{:>2}  {:<4}  {:>8}  {:>5}  {:>5}  {:>9}  {:<10}",
+            "#", "NAME", "SCORE", "LINES", "LEVEL", "TIME", "DATE"
+        )];
+        rows.extend(table.iter().enumerate().map(|(i, entry)| {
+            format!(
+                "{:>2}  {:<4}  {:>8}  {:>5}  {:>5}  {:>9}  {:<10}",
+                i + 1,
+                entry.name,
+                entry.score,
+                entry.lines,
+                entry.level,
+                format_time(entry.duration),
+                format_date(entry.date)
+            )
+        }));
+        if table.is_empty() {
+            rows.push("No scores yet".to_string());
+        }
+        for (i, row) in rows.iter().enumerate() {
+            let row_display = graphics::Text::new((row.as_str(), state.assets.font, 14.0));
+            let row_dest = Point2::new(
+                screen_w / 2.0 - row_display.width(ctx) as f32 / 2.0,
+                screen_h / 8.0 + 50.0 + 24.0 * i as f32,
+            );
+            graphics::draw(ctx, &row_display, (row_dest, 0.0, graphics::WHITE))?;
+        }
+
+        let instructions = "Left/Right: mode   Esc: back";
+        let inst_display = graphics::Text::new((instructions, state.assets.font, 14.0));
+        let inst_dest = Point2::new(
+            screen_w / 2.0 - inst_display.width(ctx) as f32 / 2.0,
+            screen_h - 40.0,
+        );
+        graphics::draw(ctx, &inst_display, (inst_dest, 0.0, graphics::WHITE))?;
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        _repeat: bool,
+    ) {
+        match keycode {
+            KeyCode::Left => self.mode = cycle(&GameMode::ALL, self.mode, -1),
+            KeyCode::Right => self.mode = cycle(&GameMode::ALL, self.mode, 1),
+            KeyCode::Escape | KeyCode::H => self.back = true,
+            _ => (),
+        }
+    }
+
+    fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
+        if let Button::East | Button::North | Button::Select = btn {
+            self.back = true;
+        }
+    }
+
+    fn gamepad_axis_event(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        match axis {
+            Axis::DPadX if value < 0.0 => self.mode = cycle(&GameMode::ALL, self.mode, -1),
+            Axis::DPadX if value > 0.0 => self.mode = cycle(&GameMode::ALL, self.mode, 1),
+            _ => (),
+        }
+    }
+}
+
+impl Scene for HighScoresScene {
+    fn shared_state(&self) -> SharedState {
+        self.state.clone()
+    }
+
+    fn get_transition(&mut self) -> Option<Transition> {
+        if self.back {
+            return Some(Transition {
+                transition_type: TransitionType::Pop,
+                scene: None,
+            });
+        }
+        None
+    }
+}
+
+// rebinds the keys and buttons for every action, one device at a time
+struct ControlsScene {
+    state: SharedState,
+    device: Device,
+    // index into Action::ALL
+    selected: usize,
+    // whether the next key or button goes to the selected action
+    waiting: bool,
+    back: bool,
+}
+
+impl ControlsScene {
+    fn new(state: &SharedState) -> Self {
+        Self {
+            state: state.clone(),
+            device: Device::Keyboard,
+            selected: 0,
+            waiting: false,
+            back: false,
+        }
+    }
+
+    fn select(&mut self, step: isize) {
+        let count = Action::ALL.len() as isize;
+        self.selected = (self.selected as isize + step).rem_euclid(count) as usize;
+    }
+
+    fn switch_device(&mut self) {
+        self.device = match self.device {
+            Device::Keyboard => Device::Gamepad,
+            Device::Gamepad => Device::Keyboard,
+        };
+    }
+
+    // applies a change to the bindings and writes them out straight away
+    fn change(&mut self, ctx: &Context, change: impl FnOnce(&mut Bindings)) {
+        let mut state = self.state.borrow_mut();
+        change(&mut state.settings.controls);
+        state.save_settings(ctx);
+    }
+
+    fn bind(&mut self, ctx: &Context, name: &str) {
+        let (device, action) = (self.device, Action::ALL[self.selected]);
+        self.change(ctx, |bindings| bindings.bind(device, action, name));
+        self.waiting = false;
+    }
+}
+
+impl EventHandler for ControlsScene {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let state = self.state.borrow();
+        let (screen_w, screen_h) = graphics::size(ctx);
+
+        // covers up the intro underneath
+        let background = graphics::Rect::new(0.0, 0.0, screen_w, screen_h);
+        let background_rect = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            background,
+            graphics::BLACK,
+        )?;
+        graphics::draw(ctx, &background_rect, graphics::DrawParam::default())?;
+
+        let title_str = format!("< {} CONTROLS >", self.device.name().to_uppercase());
+        let title_display = graphics::Text::new((title_str, state.assets.font, 24.0));
+        let title_dest = Point2::new(
+            screen_w / 2.0 - title_display.width(ctx) as f32 / 2.0,
+            screen_h / 8.0,
+        );
+        graphics::draw(ctx, &title_display, (title_dest, 0.0, graphics::WHITE))?;
+
+        for (i, &action) in Action::ALL.iter().enumerate() {
+            let names = state.settings.controls.listed(self.device, action);
+            let bound = if i == self.selected && self.waiting {
+                match self.device {
+                    Device::Keyboard => "press a key...".to_string(),
+                    Device::Gamepad => "press a button...".to_string(),
+                }
+            } else if names.is_empty() {
+                "-".to_string()
+            } else {
+                names.join(", ")
+            };
+            let line = format!("{}: {}", action.name(), bound);
+            let row_str = if i == self.selected {
+                format!("< {} >", line)
+            } else {
+                line
+            };
+            let row_display = graphics::Text::new((row_str, state.assets.font, 14.0));
+            let row_dest = Point2::new(
+                screen_w / 2.0 - row_display.width(ctx) as f32 / 2.0,
+                screen_h / 8.0 + 50.0 + 24.0 * i as f32,
+            );
+            graphics::draw(ctx, &row_display, (row_dest, 0.0, graphics::WHITE))?;
+        }
+
+        let instructions = if self.waiting {
+            "Esc: cancel"
+        } else {
+            "Enter: add   Del: clear   Tab: device   R: defaults   Esc: back"
+        };
+        let inst_display = graphics::Text::new((instructions, state.assets.font, 10.0));
+        let inst_dest = Point2::new(
+            screen_w / 2.0 - inst_display.width(ctx) as f32 / 2.0,
+            screen_h - 40.0,
+        );
+        graphics::draw(ctx, &inst_display, (inst_dest, 0.0, graphics::WHITE))?;
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        repeat: bool,
+    ) {
+        match keycode {
+            _ if repeat => (),
+            // escape always backs out, so it can't be bound from here. it's
+            // the fixed pause key in game.
+            KeyCode::Escape if self.waiting => self.waiting = false,
+            _ if self.waiting && self.device == Device::Keyboard => {
+                self.bind(ctx, &key_name(keycode))
+            }
+            _ if self.waiting => (),
+            KeyCode::Escape => self.back = true,
+            KeyCode::Up => self.select(-1),
+            KeyCode::Down => self.select(1),
+            KeyCode::Left | KeyCode::Right | KeyCode::Tab => self.switch_device(),
+            KeyCode::Return | KeyCode::NumpadEnter => self.waiting = true,
+            KeyCode::Delete | KeyCode::Back => {
+                let (device, action) = (self.device, Action::ALL[self.selected]);
+                self.change(ctx, |bindings| bindings.clear(device, action));
+            }
+            KeyCode::R => self.change(ctx, |bindings| *bindings = Bindings::default()),
+            _ => (),
+        }
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, _id: GamepadId) {
+        match btn {
+            _ if self.waiting => {
+                if self.device == Device::Gamepad {
+                    self.bind(ctx, &button_name(btn));
+                } else if btn == Button::East {
+                    self.waiting = false;
+                }
+            }
+            Button::South => self.waiting = true,
+            Button::West => {
+                let (device, action) = (self.device, Action::ALL[self.selected]);
+                self.change(ctx, |bindings| bindings.clear(device, action));
+            }
+            Button::East | Button::Select => self.back = true,
+            _ => (),
+        }
+    }
+
+    fn gamepad_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        if self.waiting {
+            if self.device == Device::Gamepad {
+                if let Some(&(btn, _)) = dpad_buttons(axis, value).iter().find(|&&(_, down)| down) {
+                    self.bind(ctx, &button_name(btn));
+                }
+            }
+            return;
+        }
+        match axis {
+            Axis::DPadX if value != 0.0 => self.switch_device(),
+            Axis::DPadY if value < 0.0 => self.select(1),
+            Axis::DPadY if value > 0.0 => self.select(-1),
+            _ => (),
+        }
+    }
+}
+
+impl Scene for ControlsScene {
+    fn shared_state(&self) -> SharedState {
+        self.state.clone()
+    }
+
+    fn get_transition(&mut self) -> Option<Transition> {
+        if self.back {
+            return Some(Transition {
+                transition_type: TransitionType::Pop,
+                scene: None,
             });
         }
         None
@@ -184,6 +814,15 @@ impl PauseScene {
             resume: false,
         }
     }
+
+    fn is_pause(&self, device: Device, name: &str) -> bool {
+        let state = self.state.borrow();
+        state
+            .settings
+            .controls
+            .actions(device, name)
+            .contains(&Action::Pause)
+    }
 }
 
 impl EventHandler for PauseScene {
@@ -215,8 +854,11 @@ impl EventHandler for PauseScene {
             .offset(Point2::new(0.5, 0.5));
         graphics::draw(ctx, &title_display, title_params)?;
 
-        let instructions = "Press start to resume";
-        let inst_display = graphics::Text::new((instructions, state.assets.font, 18.0));
+        let controls = &state.settings.controls;
+        let mut keys = controls.listed(Device::Keyboard, Action::Pause);
+        keys.extend(controls.listed(Device::Gamepad, Action::Pause));
+        let instructions = format!("{}: resume   Q: quit", keys.join("/"));
+        let inst_display = graphics::Text::new((instructions, state.assets.font, 14.0));
         let inst_dest = Point2::new(
             screen_w / 2.0 - inst_display.width(ctx) as f32 / 2.0,
             screen_h / 2.0 + 50.0,
@@ -229,10 +871,26 @@ impl EventHandler for PauseScene {
         Ok(())
     }
 
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        repeat: bool,
+    ) {
+        if repeat {
+            return;
+        }
+        if self.is_pause(Device::Keyboard, &key_name(keycode)) {
+            self.resume = true;
+        } else if keycode == KeyCode::Q {
+            ggez::event::quit(ctx);
+        }
+    }
+
     fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
-        match btn {
-            Button::Start => self.resume = true,
-            _ => (),
+        if self.is_pause(Device::Gamepad, &button_name(btn)) {
+            self.resume = true;
         }
     }
 }
@@ -255,14 +913,98 @@ impl Scene for PauseScene {
 
 struct IntroScene {
     state: SharedState,
+    options: GameOptions,
+    selected: usize,
     start_game: bool,
+    replay: Option<Replay>,
+    high_scores: Option<HighScores>,
+    controls: bool,
 }
 
 impl IntroScene {
     fn new(state: &SharedState) -> Self {
+        let options = state.borrow().settings.game.clone();
+        IntroScene::with_options(state, &options)
+    }
+
+    fn with_options(state: &SharedState, options: &GameOptions) -> Self {
         Self {
             state: state.clone(),
+            options: options.clone(),
+            selected: 0,
             start_game: false,
+            replay: None,
+            high_scores: None,
+            controls: false,
+        }
+    }
+
+    fn option_lines(&self) -> Vec<String> {
+        vec![
+            format!("Mode: {}", self.options.mode.name()),
+            format!("Start Level: {}", self.options.start_level),
+            format!("Messiness: {}%", (self.options.messiness * 100.0).round()),
+            format!("Randomizer: {}", self.options.randomizer.name()),
+            format!("Rotation: {}", self.options.rotation.name()),
+            format!("Gravity: {}", self.options.gravity.name()),
+            format!("Lock Reset: {}", self.options.lock_reset.name()),
+            format!("Previews: {}", self.options.preview_count),
+            format!(
+                "Partial Lock Out: {}",
+                if self.options.partial_lock_out {
+                    "On"
+                } else {
+                    "Off"
+                }
+            ),
+        ]
+    }
+
+    fn select(&mut self, step: isize) {
+        let count = self.option_lines().len() as isize;
+        self.selected = (self.selected as isize + step).rem_euclid(count) as usize;
+    }
+
+    fn change_option(&mut self, step: isize) {
+        match self.selected {
+            0 => self.options.mode = cycle(&GameMode::ALL, self.options.mode, step),
+            1 => {
+                let levels: Vec<u32> = (1..=MAX_START_LEVEL).collect();
+                self.options.start_level = cycle(&levels, self.options.start_level, step)
+            }
+            2 => self.options.messiness = cycle(&MESSINESS_LEVELS, self.options.messiness, step),
+            3 => {
+                self.options.randomizer = cycle(&RandomizerKind::ALL, self.options.randomizer, step)
+            }
+            4 => self.options.rotation = cycle(&RotationKind::ALL, self.options.rotation, step),
+            5 => self.options.gravity = cycle(&GravityKind::ALL, self.options.gravity, step),
+            6 => self.options.lock_reset = cycle(&LockReset::ALL, self.options.lock_reset, step),
+            7 => {
+                let counts: Vec<usize> = (1..=MAX_PREVIEW_COUNT).collect();
+                self.options.preview_count = cycle(&counts, self.options.preview_count, step)
+            }
+            8 => {
+                self.options.partial_lock_out =
+                    cycle(&[false, true], self.options.partial_lock_out, step)
+            }
+            _ => (),
+        }
+    }
+
+    // the menu's choices become the defaults for next time
+    fn save_options(&self, ctx: &Context) {
+        let mut state = self.state.borrow_mut();
+        if state.settings.game == self.options {
+            return;
+        }
+        state.settings.game = self.options.clone();
+        state.save_settings(ctx);
+    }
+
+    fn show_high_scores(&mut self, ctx: &Context) {
+        match load_user_file(ctx, HighScores::FILE_NAME) {
+            Ok(scores) => self.high_scores = Some(scores),
+            Err(e) => println!("Couldn't load high scores: {}", e),
         }
     }
 }
@@ -278,7 +1020,7 @@ impl EventHandler for IntroScene {
         let title_display = graphics::Text::new((title_str, state.assets.font, 24.0));
         let title_dest = Point2::new(
             screen_w / 2.0 - title_display.width(ctx) as f32 / 2.0,
-            screen_h / 2.0,
+            screen_h / 4.0,
         );
         let title_params = graphics::DrawParam::default()
             .dest(title_dest)
@@ -289,26 +1031,87 @@ impl EventHandler for IntroScene {
         let inst_display = graphics::Text::new((instructions, state.assets.font, 18.0));
         let inst_dest = Point2::new(
             screen_w / 2.0 - inst_display.width(ctx) as f32 / 2.0,
-            screen_h / 2.0 + 50.0,
+            screen_h / 4.0 + 50.0,
         );
         let inst_params = graphics::DrawParam::default()
             .dest(inst_dest)
             .offset(Point2::new(0.5, 0.5));
 
         graphics::draw(ctx, &inst_display, inst_params)?;
+
+        let replay_str = "R: replay   H: scores   C: controls";
+        let replay_display = graphics::Text::new((replay_str, state.assets.font, 14.0));
+        let replay_dest = Point2::new(
+            screen_w / 2.0 - replay_display.width(ctx) as f32 / 2.0,
+            screen_h / 4.0 + 74.0,
+        );
+        let replay_params = graphics::DrawParam::default()
+            .dest(replay_dest)
+            .offset(Point2::new(0.5, 0.5));
+        graphics::draw(ctx, &replay_display, replay_params)?;
+
+        for (i, line) in self.option_lines().into_iter().enumerate() {
+            let option_str = if i == self.selected {
+                format!("< {} >", line)
+            } else {
+                line
+            };
+            let option_display = graphics::Text::new((option_str, state.assets.font, 14.0));
+            let option_dest = Point2::new(
+                screen_w / 2.0 - option_display.width(ctx) as f32 / 2.0,
+                screen_h / 4.0 + 100.0 + 24.0 * i as f32,
+            );
+            let option_params = graphics::DrawParam::default()
+                .dest(option_dest)
+                .offset(Point2::new(0.5, 0.5));
+            graphics::draw(ctx, &option_display, option_params)?;
+        }
         Ok(())
     }
     fn key_down_event(
         &mut self,
-        _ctx: &mut Context,
-        _keycode: KeyCode,
+        ctx: &mut Context,
+        keycode: KeyCode,
         _keymod: KeyMods,
         _repeat: bool,
     ) {
-        self.start_game = true;
+        match keycode {
+            KeyCode::Up => self.select(-1),
+            KeyCode::Down => self.select(1),
+            KeyCode::Left => {
+                self.change_option(-1);
+                self.save_options(ctx);
+            }
+            KeyCode::Right => {
+                self.change_option(1);
+                self.save_options(ctx);
+            }
+            KeyCode::R => match load_latest_replay(ctx) {
+                Ok(Some(replay)) => self.replay = Some(replay),
+                Ok(None) => println!("No replays saved yet"),
+                Err(e) => println!("Couldn't load replay: {}", e),
+            },
+            KeyCode::H => self.show_high_scores(ctx),
+            KeyCode::C => self.controls = true,
+            _ => self.start_game = true,
+        }
     }
-    fn gamepad_button_down_event(&mut self, _ctx: &mut Context, _btn: Button, _id: GamepadId) {
-        self.start_game = true;
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, _id: GamepadId) {
+        match btn {
+            Button::North => self.show_high_scores(ctx),
+            Button::Select => self.controls = true,
+            _ => self.start_game = true,
+        }
+    }
+    fn gamepad_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        match axis {
+            Axis::DPadX if value < 0.0 => self.change_option(-1),
+            Axis::DPadX if value > 0.0 => self.change_option(1),
+            Axis::DPadY if value < 0.0 => self.select(1),
+            Axis::DPadY if value > 0.0 => self.select(-1),
+            _ => (),
+        }
+        self.save_options(ctx);
     }
 }
 
@@ -320,9 +1123,29 @@ impl Scene for IntroScene {
     fn get_transition(&mut self) -> Option<Transition> {
         if self.start_game {
             return Some(Transition {
-                scene: Some(Box::new(GameScene::new(&self.shared_state()))),
+                scene: Some(Box::new(GameScene::new(
+                    &self.shared_state(),
+                    &self.options,
+                ))),
                 transition_type: TransitionType::Swap,
             });
+        } else if let Some(replay) = self.replay.take() {
+            return watch_replay(&self.shared_state(), Some(&replay));
+        } else if let Some(scores) = self.high_scores.take() {
+            return Some(Transition {
+                transition_type: TransitionType::Push,
+                scene: Some(Box::new(HighScoresScene::new(
+                    &self.shared_state(),
+                    scores,
+                    self.options.mode,
+                ))),
+            });
+        } else if self.controls {
+            self.controls = false;
+            return Some(Transition {
+                transition_type: TransitionType::Push,
+                scene: Some(Box::new(ControlsScene::new(&self.shared_state()))),
+            });
         }
         None
     }
@@ -330,241 +1153,161 @@ impl Scene for IntroScene {
 
 struct GameScene {
     state: SharedState,
-    grid: GridState,
+    options: GameOptions,
+    game: Game,
     input: InputState,
-    falling: Option<Piece>,
-    projection: Option<Piece>,
-    held: Option<Piece>,
-    next: VecDeque<Piece>,
-    last_action: Instant,
     pause: bool,
-    score: u32,
-    level: u32,
-    lines_cleared: u32,
+    // what the last special clear was called, and how long it stays up
+    announcement: Option<(String, f32)>,
+    // what to show once the game is over, waiting to be pushed
+    ending: Option<Box<dyn Scene>>,
+    recorder: Recorder,
+    // the finished recording, once the game is over
+    replay: Option<Replay>,
 }
 
 impl GameScene {
-    fn new(state: &SharedState) -> Self {
+    fn new(state: &SharedState, options: &GameOptions) -> Self {
+        let game = Game::new(rand::random(), options);
+        println!("Game seed: {}", game.seed());
+        let recorder = Recorder::new(game.seed(), state.borrow().dt, options);
         Self {
             state: state.clone(),
-            grid: GridState([[None; COLUMNS]; ROWS]),
+            options: options.clone(),
+            game,
             input: InputState::default(),
-            falling: None,
-            projection: None,
-            held: None,
-            next: VecDeque::from(vec![
-                create_random_piece(BASE_SPEED),
-                create_random_piece(BASE_SPEED),
-                create_random_piece(BASE_SPEED),
-            ]),
-            last_action: Instant::now(),
             pause: false,
-            score: 0,
-            level: 1,
-            lines_cleared: 0,
-        }
-    }
-
-    fn swap_hold(&mut self) {
-        if self.input.hold {
-            self.input.hold = false;
-            let held_piece = self.held.take();
-            let falling_piece = self.falling.take();
-            self.falling = held_piece;
-            self.held = falling_piece;
-        }
-    }
-
-    fn create_new_piece(&mut self) {
-        match self.falling {
-            Some(_) => (),
-            None => {
-                let next = self.next.pop_front().unwrap();
-                let piece = create_random_piece(BASE_SPEED + self.level as f32 * SPEED_PER_LEVEL);
-                self.next.push_back(piece);
-                self.falling = Some(next);
-            }
-        }
-    }
-
-    fn should_end(&self) -> bool {
-        for i in 0..2 {
-            for j in 0..self.grid.0[i as usize].len() {
-                if self.grid.0[i as usize][j].is_some() {
-                    println!("no more space");
-                    return true;
-                }
-            }
-        }
-        if let Some(p) = &self.falling {
-            if !GameScene::is_valid_position(&self.grid, &p, &p.pos) {
-                return true;
-            }
-        }
-        false
-    }
-
-    fn clear_full_rows(&mut self) {
-        let mut lines_cleared: u32 = 0;
-        for i in 0..self.grid.0.len() {
-            if self.grid.0[i].iter().all(|&x| x.is_some()) {
-                for j in (0..(i + 1)).rev() {
-                    for k in 0..self.grid.0[j].len() {
-                        if j > 0 {
-                            self.grid.0[j][k] = match self.grid.0[j - 1][k] {
-                                Some(mut b) => {
-                                    b.pos = Position::new(k as f32, j as f32);
-                                    Some(b)
-                                }
-                                None => None,
-                            }
-                        } else {
-                            self.grid.0[j][k] = None;
-                        }
-                    }
-                }
-                lines_cleared += 1;
-            }
-        }
-        if lines_cleared > 0 {
-            self.lines_cleared += lines_cleared;
-            self.level = self.lines_cleared / 10 + 1;
-            let raw_score = match lines_cleared {
-                1 => 40,
-                2 => 100,
-                3 => 300,
-                4 => 1200,
-                _ => panic!("Cleared more than 4 lines?"),
-            };
-            self.score += self.level * raw_score;
-        }
-    }
-
-    fn is_valid_position(grid: &GridState, p: &Piece, pos: &Position) -> bool {
-        for block in p.get_blocks(pos) {
-            let pos = block.pos.grid_position();
-            // check if we hit the bottom
-            if pos.y as usize >= ROWS {
-                return false;
-            }
-            if pos.x < 0 || pos.x as usize >= COLUMNS {
-                return false;
-            }
-            match get_grid_idx(block.pos) {
-                Some((x, y)) => {
-                    // check if we're intersecting with a block
-                    if let Some(_) = grid.0[y][x] {
-                        return false;
-                    }
-                }
-                None => (),
-            };
-        }
-        true
-    }
-
-    fn update_piece_position(&mut self, dt: f32) {
-        if let Some(ref mut p) = self.falling {
-            let dv = p.velocity * dt;
-            let pos = p.pos.absolute_position();
-
-            let mut new_pos = Position::new(pos.x, pos.y + dv.y);
-
-            if Instant::now() - self.last_action >= Duration::from_millis(MOVEMENT_DELAY) {
-                if self.input.left {
-                    let left = new_pos.move_left();
-                    if GameScene::is_valid_position(&self.grid, p, &left) {
-                        new_pos = left;
-                    }
-                }
-                if self.input.right {
-                    let right = new_pos.move_right();
-                    if GameScene::is_valid_position(&self.grid, p, &right) {
-                        new_pos = right;
-                    }
-                }
-                if self.input.down {
-                    let down = new_pos.move_down();
-                    if GameScene::is_valid_position(&self.grid, p, &down) {
-                        new_pos = down;
-                    }
-                }
-                if self.input.moved() {
-                    self.last_action = Instant::now();
-                }
-            }
-            if Instant::now() - self.last_action >= Duration::from_millis(INPUT_DELAY) {
-                if self.input.rotate_right {
-                    p.rotate_cw();
-                    if !GameScene::is_valid_position(&self.grid, p, &new_pos) {
-                        p.rotate_ccw();
-                    }
-                }
-                if self.input.rotate_left {
-                    p.rotate_ccw();
-                    if !GameScene::is_valid_position(&self.grid, p, &new_pos) {
-                        p.rotate_cw();
-                    }
-                }
-                if self.input.up {
-                    let mut down = new_pos.move_down();
-                    let mut count = 0;
-                    while GameScene::is_valid_position(&self.grid, p, &down) {
-                        count += 1;
-                        new_pos = down;
-                        down = new_pos.move_down();
-                    }
-                    p.landed = true;
-                    self.score += count;
-                }
+            announcement: None,
+            ending: None,
+            recorder,
+            replay: None,
+        }
+    }
 
-                if self.input.acted() {
-                    self.last_action = Instant::now();
-                }
-            }
-            if p.pos.grid_position().y != new_pos.grid_position().y {
-                if !GameScene::is_valid_position(&self.grid, p, &new_pos) {
-                    // at this point, we landed on another block
-                    new_pos = Position::new(
-                        new_pos.absolute_position().x,
-                        p.pos.grid_position().y as f32,
-                    );
-                    p.landed = true;
-                }
-            }
-            p.pos = new_pos;
+    // keeps the recording around for watching straight away, and saves it
+    // with the time it finished in its name
+    fn end_recording(&mut self, ctx: &Context) {
+        let replay = self.recorder.replay().clone();
+        let mut finished = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis());
+        // never saved over another game's replay. counting up from the time
+        // keeps the newest sorting last.
+        let replay_name = |finished| format!("{}/replay-{}.toml", Replay::DIRECTORY, finished);
+        while filesystem::user_data_dir(ctx)
+            .join(replay_name(finished))
+            .exists()
+        {
+            finished += 1;
+        }
+        let name = replay_name(finished);
+        match save_user_file(ctx, &name, &replay) {
+            Ok(()) => println!("Saved replay to {}", name),
+            Err(e) => println!("Couldn't save replay: {}", e),
+        }
+        self.replay = Some(replay);
+    }
 
-            if p.landed {
-                for block in p.get_blocks(&p.pos) {
-                    match get_grid_idx(block.pos) {
-                        Some((x, y)) => {
-                            self.grid.0[y][x] = Some(Block::from_piece(p, block.pos));
-                        }
-                        None => (),
-                    }
-                }
-                self.falling = None;
-                self.projection = None;
+    // a key or button going down or up, doing whatever it's bound to
+    fn handle(&mut self, device: Device, name: &str, down: bool) {
+        let actions = self.state.borrow().settings.controls.actions(device, name);
+        for action in actions {
+            match action.input() {
+                Some(input) => self.input.set(input, down),
+                None if down => self.pause = true,
+                None => (),
             }
         }
     }
 
-    fn compute_projection_position(grid: &GridState, p: &Piece) -> Position {
-        let mut down = p.pos.clone();
-        let mut new_pos = down;
-        while GameScene::is_valid_position(grid, p, &down) {
-            new_pos = down;
-            down = down.move_down();
+    // results or game over, asking for a name first if the game made the
+    // high score table
+    fn end(&self, ctx: &Context) -> Box<dyn Scene> {
+        let mode = self.options.mode;
+        let scores: HighScores = load_user_file(ctx, HighScores::FILE_NAME).unwrap_or_else(|e| {
+            println!("Couldn't load high scores: {}", e);
+            HighScores::default()
+        });
+        let date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let entry = ScoreEntry {
+            name: String::new(),
+            score: self.game.score,
+            lines: self.game.lines_cleared,
+            level: self.game.level,
+            duration: self.game.time,
+            date,
+            seed: self.game.seed(),
+        };
+        // races only count once they're finished, and everything else once
+        // it has some points
+        let ranked = if mode.is_race() {
+            self.game.top_out.is_none()
+        } else {
+            self.game.score > 0
+        };
+        let place = if ranked && !mode.is_practice() {
+            scores.place(mode, &entry)
+        } else {
+            None
+        };
+        let next: Box<dyn Scene> = match self.game.top_out {
+            Some(reason) => Box::new(GameOverScene::new(
+                &self.state,
+                &self.options,
+                reason,
+                self.replay.clone(),
+            )),
+            None => Box::new(self.finish(&scores, place)),
+        };
+        if place.is_none() {
+            return next;
         }
-        new_pos
+        Box::new(NameEntryScene::new(
+            &self.state,
+            mode,
+            entry,
+            &scores.last_name,
+            next,
+        ))
     }
 
-    fn update_projection(&mut self) {
-        if let Some(ref p) = self.falling {
-            let mut proj = p.clone();
-            proj.pos = GameScene::compute_projection_position(&self.grid, &proj);
-            self.projection = Some(proj);
+    // sums up a completed game, with how it compares to the high score table.
+    // `place` is where it's about to go on the table, if anywhere.
+    fn finish(&self, scores: &HighScores, place: Option<usize>) -> ResultsScene {
+        let mode = self.options.mode;
+        let time = self.game.time;
+        let mut lines = vec![
+            format!("Time: {}", format_time(time)),
+            format!(
+                "Pieces: {} ({:.2}/s)",
+                self.game.pieces,
+                self.game.pieces_per_second()
+            ),
+            format!("Lines: {}", self.game.lines_cleared),
+            format!("Score: {}", self.game.score),
+        ];
+        match mode {
+            GameMode::Marathon(_) | GameMode::Zen => (),
+            GameMode::Sprint(_) | GameMode::Dig(_) => {
+                let previous = scores.table(mode).first().map(|e| e.duration);
+                lines.extend(personal_best_lines(time, previous, place == Some(0)));
+            }
+            GameMode::Ultra(_) => match (place, scores.table(mode).first()) {
+                (Some(place), _) => lines.push(format!("Leaderboard: #{}", place + 1)),
+                (None, Some(best)) => lines.push(format!("Best: {}", best.score)),
+                (None, None) => (),
+            },
         }
+        let title = format!("{} COMPLETE", mode.name().to_uppercase());
+        ResultsScene::new(
+            &self.state,
+            &self.options,
+            title,
+            lines,
+            self.replay.clone(),
+        )
     }
 }
 
@@ -574,10 +1317,10 @@ impl Scene for GameScene {
     }
 
     fn get_transition(&mut self) -> Option<Transition> {
-        if self.should_end() {
+        if let Some(ending) = self.ending.take() {
             return Some(Transition {
                 transition_type: TransitionType::Push,
-                scene: Some(Box::new(GameOverScene::new(&self.shared_state()))),
+                scene: Some(ending),
             });
         } else if self.pause {
             self.pause = false;
@@ -591,133 +1334,219 @@ impl Scene for GameScene {
 }
 
 impl EventHandler for GameScene {
-    fn update(&mut self, _ctx: &mut Context) -> GameResult<()> {
-        let shared_state = self.shared_state();
-        self.swap_hold();
-        self.create_new_piece();
-        self.update_piece_position(shared_state.borrow().dt);
-        self.clear_full_rows();
-        self.update_projection();
+    fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
+        let dt = self.state.borrow().dt;
+        self.recorder.record(&self.input);
+        self.game.step(&self.input, dt);
+        let ended = self.game.is_complete() || self.game.top_out.is_some();
+        if ended && self.replay.is_none() {
+            self.end_recording(ctx);
+            self.ending = Some(self.end(ctx));
+        }
+        for event in self.game.take_events() {
+            if let Some(name) = event.name() {
+                self.announcement = Some((name, ANNOUNCE_TIME));
+            }
+        }
+        if let Some((_, ref mut remaining)) = self.announcement {
+            *remaining -= dt;
+            if *remaining <= 0.0 {
+                self.announcement = None;
+            }
+        }
         Ok(())
     }
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
         let s = self.shared_state();
         let state = s.borrow();
-        {
-            let assets = &state.assets;
-            {
-                let _lock = graphics::use_shader(ctx, &state.shader);
-                state.shader.send(ctx, state.opacity)?;
+        draw_game(
+            ctx,
+            &state,
+            &self.game,
+            self.options.mode,
+            self.announcement.as_ref(),
+        )
+    }
 
-                if let Some(p) = &self.projection {
-                    for b in p.get_blocks(&p.pos) {
-                        draw_block(assets, ctx, b, state.screen_params)?;
-                    }
-                }
-            }
-            if let Some(p) = &self.falling {
-                for b in p.get_blocks(&p.pos) {
-                    draw_block(assets, ctx, b, state.screen_params)?;
-                }
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        repeat: bool,
+    ) {
+        if !repeat {
+            self.handle(Device::Keyboard, &key_name(keycode), true);
+        }
+    }
+
+    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        self.handle(Device::Keyboard, &key_name(keycode), false);
+    }
+
+    fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
+        self.handle(Device::Gamepad, &button_name(btn), true);
+    }
+
+    fn gamepad_button_up_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
+        self.handle(Device::Gamepad, &button_name(btn), false);
+    }
+
+    fn gamepad_axis_event(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        for (btn, down) in dpad_buttons(axis, value) {
+            self.handle(Device::Gamepad, &button_name(btn), down);
+        }
+    }
+}
+
+// plays a recorded game back, with controls to pause, step, change speed and
+// seek
+struct ReplayScene {
+    state: SharedState,
+    playback: Playback,
+    // index into REPLAY_SPEEDS
+    speed: usize,
+    paused: bool,
+    // frames owed to the playback, which builds up slower than one per update
+    // below normal speed
+    frames_due: f32,
+    announcement: Option<(String, f32)>,
+    back: bool,
+}
+
+impl ReplayScene {
+    fn new(state: &SharedState, replay: Replay) -> Self {
+        Self {
+            state: state.clone(),
+            playback: Playback::new(replay),
+            speed: REPLAY_SPEEDS.iter().position(|&s| s == 1.0).unwrap_or(0),
+            paused: false,
+            frames_due: 0.0,
+            announcement: None,
+            back: false,
+        }
+    }
+
+    fn step(&mut self) {
+        self.playback.step();
+        for event in self.playback.take_events() {
+            if let Some(name) = event.name() {
+                self.announcement = Some((name, ANNOUNCE_TIME));
             }
-            for i in 0..self.grid.0.len() {
-                for j in 0..self.grid.0[i].len() {
-                    if let Some(b) = self.grid.0[i][j] {
-                        draw_block(assets, ctx, b, state.screen_params)?;
-                    }
-                }
+        }
+        // announcements go by game time, so they keep up with the speed
+        let dt = self.playback.replay().dt;
+        if let Some((_, ref mut remaining)) = self.announcement {
+            *remaining -= dt;
+            if *remaining <= 0.0 {
+                self.announcement = None;
             }
         }
+    }
+
+    fn seek(&mut self, seconds: f32) {
+        let frames = (seconds / self.playback.replay().dt).round() as i64;
+        let target = (i64::from(self.playback.frame()) + frames).max(0);
+        self.playback.seek(target as u32);
+        self.announcement = None;
+    }
+}
+
+impl EventHandler for ReplayScene {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        if self.paused || self.playback.is_finished() {
+            return Ok(());
+        }
+        self.frames_due += REPLAY_SPEEDS[self.speed];
+        while self.frames_due >= 1.0 {
+            self.step();
+            self.frames_due -= 1.0;
+        }
+        Ok(())
+    }
 
-        let box_position = Point2::new(state.screen_params.1, state.screen_params.2);
-        graphics::draw(ctx, &state.border_box, (box_position,))?;
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let state = self.state.borrow();
+        let (screen_w, screen_h) = graphics::size(ctx);
 
-        let score_dest = Point2::new(10.0, 30.0);
-        let score_str = format!("Score: {}", self.score);
-        let score_display = graphics::Text::new((score_str, state.assets.font, 14.0));
-        graphics::draw(ctx, &score_display, (score_dest, 0.0, graphics::WHITE))?;
+        // covers up whichever scene the replay was opened from
+        let background = graphics::Rect::new(0.0, 0.0, screen_w, screen_h);
+        let background_rect = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            background,
+            graphics::BLACK,
+        )?;
+        graphics::draw(ctx, &background_rect, graphics::DrawParam::default())?;
 
-        let level_dest = Point2::new(10.0, 50.0);
-        let level_str = format!("Level: {}", self.level);
-        let level_display = graphics::Text::new((level_str, state.assets.font, 14.0));
-        graphics::draw(ctx, &level_display, (level_dest, 0.0, graphics::WHITE))?;
+        let replay = self.playback.replay();
+        draw_game(
+            ctx,
+            &state,
+            self.playback.game(),
+            replay.options.mode,
+            self.announcement.as_ref(),
+        )?;
 
+        let status = if self.paused {
+            "PAUSED"
+        } else if self.playback.is_finished() {
+            "END"
+        } else {
+            ""
+        };
+        let status_str = format!(
+            "REPLAY {} / {}  x{}  {}",
+            format_time(self.playback.frame() as f32 * replay.dt),
+            format_time(replay.frames as f32 * replay.dt),
+            REPLAY_SPEEDS[self.speed],
+            status
+        );
+        let status_display = graphics::Text::new((status_str, state.assets.font, 14.0));
+        let status_dest = Point2::new(10.0, screen_h - 44.0);
+        graphics::draw(ctx, &status_display, (status_dest, 0.0, graphics::WHITE))?;
+
+        let instructions = "Space: pause  .: step  Up/Down: speed  Left/Right: seek  Esc: back";
+        let inst_display = graphics::Text::new((instructions, state.assets.font, 10.0));
+        let inst_dest = Point2::new(10.0, screen_h - 20.0);
+        graphics::draw(ctx, &inst_display, (inst_dest, 0.0, graphics::WHITE))?;
         Ok(())
     }
 
     fn key_down_event(
         &mut self,
-        ctx: &mut Context,
+        _ctx: &mut Context,
         keycode: KeyCode,
         _keymod: KeyMods,
         _repeat: bool,
     ) {
         match keycode {
-            KeyCode::Escape => ggez::event::quit(ctx),
-            KeyCode::Space => self.pause = true,
-            KeyCode::Left => self.input.left = true,
-            KeyCode::Right => self.input.right = true,
-            KeyCode::Z => self.input.rotate_left = true,
-            KeyCode::X => self.input.rotate_right = true,
-            KeyCode::Up => self.input.up = true,
-            KeyCode::Down => self.input.down = true,
-            _ => (),
-        }
-    }
-
-    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
-        match keycode {
-            KeyCode::Left => self.input.left = false,
-            KeyCode::Right => self.input.right = false,
-            KeyCode::Z => self.input.rotate_left = false,
-            KeyCode::X => self.input.rotate_right = false,
-            KeyCode::Up => self.input.up = false,
-            KeyCode::Down => self.input.down = false,
-            _ => (),
-        }
-    }
-
-    fn gamepad_button_down_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
-        match btn {
-            Button::West => self.input.rotate_left = true,
-            Button::South => self.input.rotate_right = true,
-            Button::Start => self.pause = true,
+            KeyCode::Escape => self.back = true,
+            KeyCode::Space => self.paused = !self.paused,
+            KeyCode::Period if self.paused => self.step(),
+            KeyCode::Up => self.speed = (self.speed + 1).min(REPLAY_SPEEDS.len() - 1),
+            KeyCode::Down => self.speed = self.speed.saturating_sub(1),
+            KeyCode::Left => self.seek(-SEEK_SECONDS),
+            KeyCode::Right => self.seek(SEEK_SECONDS),
+            KeyCode::Home => self.playback.seek(0),
             _ => (),
         }
     }
+}
 
-    fn gamepad_button_up_event(&mut self, _ctx: &mut Context, btn: Button, _id: GamepadId) {
-        match btn {
-            Button::West => self.input.rotate_left = false,
-            Button::South => self.input.rotate_right = false,
-            _ => (),
-        }
+impl Scene for ReplayScene {
+    fn shared_state(&self) -> SharedState {
+        self.state.clone()
     }
 
-    fn gamepad_axis_event(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
-        match axis {
-            Axis::DPadX => {
-                if value < 0.0 {
-                    self.input.left = true;
-                } else if value > 0.0 {
-                    self.input.right = true;
-                } else {
-                    self.input.left = false;
-                    self.input.right = false;
-                }
-            }
-            Axis::DPadY => {
-                if value < 0.0 {
-                    self.input.down = true;
-                } else if value > 0.0 {
-                    self.input.up = true;
-                } else {
-                    self.input.down = false;
-                    self.input.up = false;
-                }
-            }
-            _ => (),
+    fn get_transition(&mut self) -> Option<Transition> {
+        if self.back {
+            return Some(Transition {
+                transition_type: TransitionType::Pop,
+                scene: None,
+            });
         }
+        None
     }
 }
 
@@ -727,8 +1556,21 @@ pub struct SceneManager {
 }
 
 impl SceneManager {
-    pub fn new(ctx: &mut Context) -> GameResult<SceneManager> {
-        let state = ContextBoundState::new(ctx)?;
+    // `settings_loaded` is false if the settings file is there but couldn't be
+    // read, and `settings` are the defaults standing in for it
+    pub fn new(
+        ctx: &mut Context,
+        settings: Settings,
+        settings_loaded: bool,
+    ) -> GameResult<SceneManager> {
+        // writes out the defaults the first time, so there's a file to edit
+        let settings_path = filesystem::user_data_dir(ctx).join(Settings::FILE_NAME);
+        if !settings_path.exists() {
+            if let Err(e) = save_user_file(ctx, Settings::FILE_NAME, &settings) {
+                println!("Couldn't save settings: {}", e);
+            }
+        }
+        let state = ContextBoundState::new(ctx, settings, settings_loaded)?;
         let state_wrapper = Rc::new(RefCell::new(state));
         let sm = Self {
             scenes: vec![Box::new(IntroScene::new(&state_wrapper))],
@@ -746,15 +1588,25 @@ struct ContextBoundState {
     shader: graphics::Shader<Opacity>,
     dt: f32,
     fps: f64,
+    settings: Settings,
+    // false while the settings file couldn't be read, so it's left alone for
+    // the player to fix rather than saved over with the defaults
+    settings_loaded: bool,
 }
 
 impl ContextBoundState {
-    pub fn new(ctx: &mut Context) -> GameResult<ContextBoundState> {
+    pub fn new(
+        ctx: &mut Context,
+        settings: Settings,
+        settings_loaded: bool,
+    ) -> GameResult<ContextBoundState> {
         println!("Game resource path: {:?}", ctx.filesystem);
 
         let assets = Assets::new(ctx)?;
 
-        let opacity = Opacity { pct: 0.05 };
+        let opacity = Opacity {
+            pct: settings.display.ghost_opacity,
+        };
         let shader = graphics::Shader::new(
             ctx,
             "/basic_150.glslv",
@@ -773,11 +1625,26 @@ impl ContextBoundState {
             fps: 0.0,
             dt: 0.0,
             screen_params,
-            border_box: build_border_box(ctx, screen_params.3, screen_params.4)?,
+            border_box: build_border_box(ctx, screen_params)?,
+            settings,
+            settings_loaded,
         };
 
         Ok(s)
     }
+
+    fn save_settings(&self, ctx: &Context) {
+        if !self.settings_loaded {
+            println!(
+                "Not saving settings over {}, since it couldn't be loaded",
+                Settings::FILE_NAME
+            );
+            return;
+        }
+        if let Err(e) = save_user_file(ctx, Settings::FILE_NAME, &self.settings) {
+            println!("Couldn't save settings: {}", e);
+        }
+    }
 }
 
 impl EventHandler for SceneManager {
@@ -833,10 +1700,12 @@ impl EventHandler for SceneManager {
 
         let shared_state = self.state.borrow();
 
-        let fps_dest = Point2::new(10.0, 10.0);
-        let fps_str = format!("FPS: {:.2}", shared_state.fps);
-        let fps_display = graphics::Text::new((fps_str, shared_state.assets.font, 14.0));
-        graphics::draw(ctx, &fps_display, (fps_dest, 0.0, graphics::WHITE))?;
+        if shared_state.settings.display.show_fps {
+            let fps_dest = Point2::new(10.0, 10.0);
+            let fps_str = format!("FPS: {:.2}", shared_state.fps);
+            let fps_display = graphics::Text::new((fps_str, shared_state.assets.font, 14.0));
+            graphics::draw(ctx, &fps_display, (fps_dest, 0.0, graphics::WHITE))?;
+        }
 
         graphics::present(ctx)?;
 
@@ -862,6 +1731,12 @@ impl EventHandler for SceneManager {
         };
     }
 
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) {
+        if let Some(s) = self.scenes.last_mut() {
+            s.text_input_event(ctx, character);
+        };
+    }
+
     fn gamepad_button_down_event(&mut self, ctx: &mut Context, btn: Button, id: GamepadId) {
         if let Some(s) = self.scenes.last_mut() {
             s.gamepad_button_down_event(ctx, btn, id);
@@ -887,7 +1762,7 @@ impl EventHandler for SceneManager {
         let screen_params = compute_screen_params(graphics::size(ctx));
         let mut state = self.state.borrow_mut();
         state.screen_params = screen_params;
-        state.border_box = build_border_box(ctx, screen_params.3, screen_params.4).unwrap();
+        state.border_box = build_border_box(ctx, screen_params).unwrap();
 
         if let Some(s) = self.scenes.last_mut() {
             s.resize_event(ctx, width, height);
@@ -895,12 +1770,296 @@ impl EventHandler for SceneManager {
     }
 }
 
-fn get_grid_idx(p: Position) -> Option<(usize, usize)> {
-    let pos = p.grid_position();
-    if pos.x < 0 || pos.x >= COLUMNS as i32 || pos.y < 0 || pos.y >= ROWS as i32 {
-        return None;
+// minutes, seconds and milliseconds, like 1:02.345
+fn format_time(seconds: f32) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u32;
+    format!(
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+// how a finished race compares to the old personal best
+fn personal_best_lines(time: f32, previous: Option<f32>, new_best: bool) -> Vec<String> {
+    let mut lines = vec![];
+    if new_best {
+        lines.push("NEW PERSONAL BEST".to_string());
+        if let Some(best) = previous {
+            lines.push(format!("Previous best: {}", format_time(best)));
+        }
+    } else if let Some(best) = previous {
+        lines.push(format!(
+            "Best: {} (+{})",
+            format_time(best),
+            format_time(time - best)
+        ));
+    }
+    lines
+}
+
+// reads a toml file from the user data directory, or the default value if
+// it hasn't been written yet
+fn load_user_file<T: DeserializeOwned + Default>(ctx: &Context, name: &str) -> GameResult<T> {
+    let path = filesystem::user_data_dir(ctx).join(name);
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let text = fs::read_to_string(&path)?;
+    Ok(toml::from_str(&text)?)
+}
+
+fn save_user_file<T: Serialize>(ctx: &Context, name: &str, value: &T) -> GameResult {
+    let path = filesystem::user_data_dir(ctx).join(name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // going through a toml::Value puts tables after plain values, which
+    // toml needs and structs don't always declare their fields in
+    let value = toml::Value::try_from(value)?;
+    fs::write(path, toml::to_string(&value)?)?;
+    Ok(())
+}
+
+// the most recently saved replay, if there are any
+fn load_latest_replay(ctx: &Context) -> GameResult<Option<Replay>> {
+    let dir = filesystem::user_data_dir(ctx).join(Replay::DIRECTORY);
+    if !dir.exists() {
+        return Ok(None);
+    }
+    // replays are named after when they were saved, so the newest sorts last
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        paths.push(entry?.path());
+    }
+    let latest = match paths.into_iter().max() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let replay: Replay = toml::from_str(&fs::read_to_string(&latest)?)?;
+    if replay.version != Replay::VERSION {
+        return Err(GameError::ResourceLoadError(format!(
+            "{} is a version {} replay, and only version {} can be played",
+            latest.display(),
+            replay.version,
+            Replay::VERSION
+        )));
+    }
+    Ok(Some(replay))
+}
+
+fn watch_replay(state: &SharedState, replay: Option<&Replay>) -> Option<Transition> {
+    let replay = replay?;
+    Some(Transition {
+        transition_type: TransitionType::Push,
+        scene: Some(Box::new(ReplayScene::new(state, replay.clone()))),
+    })
+}
+
+// the names bindings know keys and buttons by
+fn key_name(keycode: KeyCode) -> String {
+    format!("{:?}", keycode)
+}
+
+fn button_name(btn: Button) -> String {
+    format!("{:?}", btn)
+}
+
+// some gamepads report the d-pad as a pair of axes instead of buttons, so
+// this turns a move of either axis back into the buttons going down or up
+fn dpad_buttons(axis: Axis, value: f32) -> Vec<(Button, bool)> {
+    let (negative, positive) = match axis {
+        Axis::DPadX => (Button::DPadLeft, Button::DPadRight),
+        Axis::DPadY => (Button::DPadDown, Button::DPadUp),
+        _ => return vec![],
+    };
+    vec![(negative, value < 0.0), (positive, value > 0.0)]
+}
+
+// steps through a list of choices, wrapping around at either end
+fn cycle<T: Copy + PartialEq>(all: &[T], current: T, step: isize) -> T {
+    let i = all.iter().position(|&x| x == current).unwrap_or(0) as isize;
+    all[(i + step).rem_euclid(all.len() as isize) as usize]
+}
+
+// the board and everything around it, for a game being played or replayed
+fn draw_game(
+    ctx: &mut Context,
+    state: &ContextBoundState,
+    game: &Game,
+    mode: GameMode,
+    announcement: Option<&(String, f32)>,
+) -> GameResult {
+    {
+        let assets = &state.assets;
+        // the buffer rows above the field are kept hidden
+        let visible = |b: &Block| b.pos.grid_position().y >= BUFFER_ROWS as i32;
+        {
+            let _lock = graphics::use_shader(ctx, &state.shader);
+            state.shader.send(ctx, state.opacity)?;
+
+            if let Some(p) = &game.projection {
+                for b in p.get_blocks(&p.pos).into_iter().filter(visible) {
+                    draw_block(assets, ctx, b, state.screen_params, graphics::WHITE)?;
+                }
+            }
+        }
+        if let Some(p) = &game.falling {
+            for b in p.get_blocks(&p.pos).into_iter().filter(visible) {
+                draw_block(assets, ctx, b, state.screen_params, graphics::WHITE)?;
+            }
+        }
+        let garbage_top = ROWS - game.garbage_left as usize;
+        for (y, row) in game.grid.0.iter().enumerate().skip(BUFFER_ROWS) {
+            let color = if y >= garbage_top {
+                graphics::Color::new(0.5, 0.5, 0.5, 1.0)
+            } else {
+                graphics::WHITE
+            };
+            for b in row.iter().flatten() {
+                draw_block(assets, ctx, *b, state.screen_params, color)?;
+            }
+        }
+    }
+
+    let box_position = Point2::new(state.screen_params.1, state.screen_params.2);
+    graphics::draw(ctx, &state.border_box, (box_position,))?;
+
+    let lock_progress = game.lock_progress();
+    if lock_progress > 0.0 {
+        let (_, x_offset, y_offset, x_extent, y_extent) = state.screen_params;
+        let lock_bar = graphics::Rect::new(
+            x_offset,
+            y_offset + y_extent + 4.0,
+            x_extent * lock_progress,
+            4.0,
+        );
+        let lock_mesh = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            lock_bar,
+            graphics::WHITE,
+        )?;
+        graphics::draw(ctx, &lock_mesh, graphics::DrawParam::default())?;
+    }
+
+    let (block_size, x_offset, y_offset, x_extent, _) = state.screen_params;
+    let left_column = x_offset - 6.0 * block_size;
+    let right_column = x_offset + x_extent + block_size;
+    let top = y_offset + 2.0 * block_size;
+
+    let hold_dest = Point2::new(left_column, top);
+    let hold_color = if game.can_hold() {
+        graphics::WHITE
+    } else {
+        graphics::Color::new(0.4, 0.4, 0.4, 1.0)
+    };
+    draw_piece_box(
+        state,
+        ctx,
+        "Hold",
+        game.held.as_ref(),
+        hold_dest,
+        block_size,
+        hold_color,
+    )?;
+
+    let mut stats = vec![
+        ("Score", game.score.to_string()),
+        ("Level", game.level.to_string()),
+    ];
+    let pps = format!("{:.2}", game.pieces_per_second());
+    match mode {
+        GameMode::Marathon(None) => stats.push(("Lines", game.lines_cleared.to_string())),
+        GameMode::Marathon(Some(goal)) => {
+            stats.push(("Lines", format!("{}/{}", game.lines_cleared, goal)))
+        }
+        GameMode::Sprint(goal) => {
+            stats.push(("Lines", format!("{}/{}", game.lines_cleared, goal)));
+            stats.push(("Time", format_time(game.time)));
+            stats.push(("PPS", pps));
+        }
+        GameMode::Ultra(seconds) => {
+            // counts down rather than up
+            let remaining = seconds as f32 - game.time;
+            stats.push(("Lines", game.lines_cleared.to_string()));
+            stats.push(("Time", format_time(remaining)));
+            stats.push(("PPS", pps));
+        }
+        GameMode::Dig(_) => {
+            stats.push(("Garbage", game.garbage_left.to_string()));
+            stats.push(("Time", format_time(game.time)));
+            stats.push(("PPS", pps));
+        }
+        GameMode::Zen => {
+            stats.push(("Lines", game.lines_cleared.to_string()));
+            stats.push(("PPS", pps));
+            stats.push(("Undo", game.undo_count().to_string()));
+        }
+    }
+    stats.push(("Combo", game.chain.combo.unwrap_or(0).to_string()));
+    stats.push(("B2B", game.chain.back_to_back.saturating_sub(1).to_string()));
+    let text_size = (block_size * 0.6).round().max(8.0);
+    for (i, (label, value)) in stats.iter().enumerate() {
+        let stat_dest = Point2::new(
+            left_column,
+            top + 5.0 * block_size + i as f32 * 2.5 * text_size,
+        );
+        let stat_str = format!("{}\n{}", label, value);
+        let stat_display = graphics::Text::new((stat_str, state.assets.font, text_size));
+        graphics::draw(ctx, &stat_display, (stat_dest, 0.0, graphics::WHITE))?;
+    }
+
+    if let Some((name, remaining)) = announcement {
+        let announce_dest = Point2::new(
+            left_column,
+            top + 5.0 * block_size + (stats.len() as f32 * 2.5 + 1.0) * text_size,
+        );
+        let announce_color = graphics::Color::new(1.0, 1.0, 1.0, remaining.min(1.0));
+        let mut announce_display =
+            graphics::Text::new((name.as_str(), state.assets.font, text_size));
+        // wrap long names like "MINI T-SPIN SINGLE" to the column
+        announce_display.set_bounds(
+            Point2::new(5.0 * block_size, f32::INFINITY),
+            graphics::Align::Left,
+        );
+        graphics::draw(ctx, &announce_display, (announce_dest, 0.0, announce_color))?;
+    }
+
+    let mut previews = game.next.iter();
+    let next_dest = Point2::new(right_column, top);
+    draw_piece_box(
+        state,
+        ctx,
+        "Next",
+        previews.next(),
+        next_dest,
+        block_size,
+        graphics::WHITE,
+    )?;
+    let small_block = 0.6 * block_size;
+    for (i, p) in previews.enumerate() {
+        let center = Point2::new(
+            right_column + 2.5 * block_size,
+            top + 5.5 * block_size + i as f32 * 3.0 * small_block,
+        );
+        draw_piece_preview(&state.assets, ctx, p, center, small_block, graphics::WHITE)?;
+    }
+
+    let countdown = game.countdown();
+    if countdown > 0.0 {
+        let (_, x_offset, y_offset, x_extent, y_extent) = state.screen_params;
+        let count_str = format!("{}", countdown.ceil());
+        let count_display = graphics::Text::new((count_str, state.assets.font, 48.0));
+        let count_dest = Point2::new(
+            x_offset + x_extent / 2.0 - count_display.width(ctx) as f32 / 2.0,
+            y_offset + y_extent / 3.0,
+        );
+        graphics::draw(ctx, &count_display, (count_dest, 0.0, graphics::WHITE))?;
     }
-    Some((pos.x as usize, pos.y as usize))
+
+    Ok(())
 }
 
 fn draw_block(
@@ -908,6 +2067,7 @@ fn draw_block(
     ctx: &mut Context,
     block: Block,
     screen_params: (f32, f32, f32, f32, f32),
+    color: graphics::Color,
 ) -> GameResult {
     let image = assets.get_image_for_block(&block);
     let (block_width, x_offset, y_offset, _, _) = screen_params;
@@ -915,10 +2075,74 @@ fn draw_block(
 
     let draw_params = graphics::DrawParam::new()
         .dest(block.pos.screen_coords(block_width, x_offset, y_offset))
-        .scale(Vec2::new(scale, scale));
+        .scale(Vec2::new(scale, scale))
+        .color(color);
     graphics::draw(ctx, image, draw_params)
 }
 
+// draws a titled box with a piece centered inside, like the hold slot
+fn draw_piece_box(
+    state: &ContextBoundState,
+    ctx: &mut Context,
+    title: &str,
+    piece: Option<&Piece>,
+    dest: Point2,
+    block_size: f32,
+    color: graphics::Color,
+) -> GameResult {
+    let title_display = graphics::Text::new((title, state.assets.font, 14.0));
+    graphics::draw(ctx, &title_display, (dest, 0.0, color))?;
+
+    let box_top = dest.y + 20.0;
+    let (box_w, box_h) = (5.0 * block_size, 3.0 * block_size);
+    let outline = graphics::Mesh::new_rectangle(
+        ctx,
+        graphics::DrawMode::stroke(2.0),
+        graphics::Rect::new(dest.x, box_top, box_w, box_h),
+        color,
+    )?;
+    graphics::draw(ctx, &outline, graphics::DrawParam::default())?;
+
+    if let Some(p) = piece {
+        let center = Point2::new(dest.x + box_w / 2.0, box_top + box_h / 2.0);
+        draw_piece_preview(&state.assets, ctx, p, center, 0.8 * block_size, color)?;
+    }
+    Ok(())
+}
+
+// draws a piece in its spawn orientation centered on a point, outside the grid
+fn draw_piece_preview(
+    assets: &Assets,
+    ctx: &mut Context,
+    piece: &Piece,
+    center: Point2,
+    block_size: f32,
+    color: graphics::Color,
+) -> GameResult {
+    let blocks = piece.get_blocks(&Position::new(0.0, 0.0));
+    let cells: Vec<_> = blocks.iter().map(|b| b.pos.grid_position()).collect();
+    let min_x = cells.iter().map(|c| c.x).min().unwrap_or(0);
+    let max_x = cells.iter().map(|c| c.x).max().unwrap_or(0);
+    let min_y = cells.iter().map(|c| c.y).min().unwrap_or(0);
+    let max_y = cells.iter().map(|c| c.y).max().unwrap_or(0);
+    let width = (max_x - min_x + 1) as f32 * block_size;
+    let height = (max_y - min_y + 1) as f32 * block_size;
+    let scale = block_size / 160.0;
+
+    for (block, cell) in blocks.iter().zip(cells.iter()) {
+        let dest = Point2::new(
+            center.x - width / 2.0 + (cell.x - min_x) as f32 * block_size,
+            center.y - height / 2.0 + (cell.y - min_y) as f32 * block_size,
+        );
+        let draw_params = graphics::DrawParam::new()
+            .dest(dest)
+            .scale(Vec2::new(scale, scale))
+            .color(color);
+        graphics::draw(ctx, assets.get_image_for_block(block), draw_params)?;
+    }
+    Ok(())
+}
+
 fn compute_screen_params(window_size: (f32, f32)) -> (f32, f32, f32, f32, f32) {
     let mut y_extent = (window_size.0 / ASPECT_RATIO).min(window_size.1);
     let y_offset = 0.5 * (window_size.1 - y_extent);
@@ -938,16 +2162,22 @@ fn compute_screen_params(window_size: (f32, f32)) -> (f32, f32, f32, f32, f32) {
     )
 }
 
-fn build_border_box(ctx: &mut Context, x_extent: f32, y_extent: f32) -> GameResult<graphics::Mesh> {
+// outlines the visible field, leaving the buffer rows above it open
+fn build_border_box(
+    ctx: &mut Context,
+    screen_params: (f32, f32, f32, f32, f32),
+) -> GameResult<graphics::Mesh> {
+    let (block_size, _, _, x_extent, y_extent) = screen_params;
+    let top = BUFFER_ROWS as f32 * block_size;
     let mb = &mut graphics::MeshBuilder::new();
 
     mb.line(
         &[
-            Point2::new(0.0, 0.0),
-            Point2::new(x_extent, 0.0),
+            Point2::new(0.0, top),
+            Point2::new(x_extent, top),
             Point2::new(x_extent, y_extent),
             Point2::new(0.0, y_extent),
-            Point2::new(0.0, 0.0),
+            Point2::new(0.0, top),
         ],
         2.0,
         graphics::WHITE,
@@ -955,122 +2185,3 @@ fn build_border_box(ctx: &mut Context, x_extent: f32, y_extent: f32) -> GameResu
 
     mb.build(ctx)
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use ggez::ContextBuilder;
-    use std::env;
-    use std::path;
-
-    macro_rules! create_game_state {
-        () => {{
-            let resource_dir = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
-                let mut path = path::PathBuf::from(manifest_dir);
-                path.push("resources");
-                path
-            } else {
-                println!("backup");
-                path::PathBuf::from("./resources")
-            };
-
-            let cb = ContextBuilder::new("test", "test").add_resource_path(resource_dir);
-            let (ctx, _) = &mut cb.build().unwrap();
-            let sm = &mut SceneManager::new(ctx).unwrap();
-            GameScene::new(&sm.state)
-        }};
-    }
-
-    const DT: f32 = 1.0 / 60.0;
-
-    #[test]
-    fn create_new_piece_calls_if_none() {
-        let mut state = create_game_state!();
-        state.create_new_piece();
-        assert!(state.falling.is_some());
-    }
-
-    #[test]
-    fn no_falling_piece_at_start() {
-        let state = create_game_state!();
-        assert!(state.falling.is_none());
-    }
-
-    #[test]
-    fn piece_position_falling() {
-        let mut state = create_game_state!();
-        state.create_new_piece();
-        let initial_pos = state.falling.as_ref().unwrap().pos.clone();
-        state.update_piece_position(DT);
-        let p = state.falling.unwrap();
-
-        assert_eq!(
-            p.pos.absolute_position().y,
-            initial_pos.absolute_position().y + p.velocity.y * DT
-        );
-        assert_eq!(
-            p.pos.absolute_position().x,
-            initial_pos.absolute_position().x
-        );
-    }
-
-    #[test]
-    fn piece_position_left_input() {
-        let mut state = create_game_state!();
-        state.create_new_piece();
-        let initial_pos = state.falling.as_ref().unwrap().pos.clone();
-        state.input.left = true;
-        state.last_action = Instant::now() - Duration::from_millis(INPUT_DELAY);
-        state.update_piece_position(DT);
-        let p = state.falling.unwrap();
-
-        assert_eq!(
-            p.pos.absolute_position().y,
-            initial_pos.absolute_position().y + p.velocity.y * DT
-        );
-        assert_eq!(
-            p.pos.absolute_position().x,
-            initial_pos.absolute_position().x - 1.0
-        );
-    }
-
-    #[test]
-    fn piece_position_right_input() {
-        let mut state = create_game_state!();
-        state.create_new_piece();
-        let initial_pos = state.falling.as_ref().unwrap().pos.clone();
-        state.input.right = true;
-        state.last_action = Instant::now() - Duration::from_millis(INPUT_DELAY);
-        state.update_piece_position(DT);
-        let p = state.falling.unwrap();
-
-        assert_eq!(
-            p.pos.absolute_position().y,
-            initial_pos.absolute_position().y + p.velocity.y * DT
-        );
-        assert_eq!(
-            p.pos.absolute_position().x,
-            initial_pos.absolute_position().x + 1.0
-        );
-    }
-
-    #[test]
-    fn piece_position_soft_down_input() {
-        let mut state = create_game_state!();
-        state.create_new_piece();
-        let initial_pos = state.falling.as_ref().unwrap().pos.clone();
-        state.input.down = true;
-        state.last_action = Instant::now() - Duration::from_millis(INPUT_DELAY);
-        state.update_piece_position(DT);
-        let p = state.falling.unwrap();
-
-        assert_eq!(
-            p.pos.absolute_position().y,
-            (initial_pos.absolute_position().y + p.velocity.y * DT).ceil()
-        );
-        assert_eq!(
-            p.pos.absolute_position().x,
-            initial_pos.absolute_position().x
-        );
-    }
-}
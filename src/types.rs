@@ -2,5 +2,4 @@ extern crate nalgebra;
 
 pub type Point2 = nalgebra::Point2<f32>;
 pub type Vec2 = nalgebra::Vector2<f32>;
-pub type Rot2 = nalgebra::Rotation2<f32>;
 pub type GridPosition = nalgebra::Vector2<i32>;